@@ -0,0 +1,163 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::env;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+/// Persistent user configuration, stored as a TOML file.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Config {
+    /// Update interval in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// Maximum time to wait for streams to provide their values, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Whether to leave out network streams for the loopback interface and container interfaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_virtual_interfaces: Option<bool>,
+    /// Stream settings, in the order in which the streams are displayed
+    #[serde(default)]
+    pub streams: Vec<StreamConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StreamConfig {
+    /// Unique name of the stream, as returned by `Stream::name`
+    pub name: String,
+    #[serde(default = "default_active")]
+    pub active: bool,
+    #[serde(default)]
+    pub expanded: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl Config {
+    /// Returns the default location of the configuration file,
+    /// following the XDG Base Directory Specification.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match env::var_os("XDG_CONFIG_HOME") {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(config_home.join("hegemon").join("config.toml"))
+    }
+
+    /// Loads the configuration from the given file.
+    /// A missing file results in the default configuration.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(string) => toml::from_str(&string).map_err(|error| Error::new(ErrorKind::InvalidData, error)),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Saves the configuration to the given file,
+    /// creating parent directories as required.
+    /// The file is replaced atomically, so it is never left half-written
+    /// if the application is terminated while saving.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let string = toml::to_string(self).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        // Renaming only replaces the file atomically within the same filesystem,
+        // so the temporary file is created next to it
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(format!(".{}.tmp", process::id()));
+        let temporary_path = PathBuf::from(temporary_path);
+
+        fs::write(&temporary_path, string)
+            .and_then(|_| fs::rename(&temporary_path, path))
+            .inspect_err(|_| {
+                fs::remove_file(&temporary_path).ok();
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = Config {
+            interval: Some(2_000),
+            timeout: Some(300),
+            exclude_virtual_interfaces: Some(true),
+            streams: vec![
+                StreamConfig {
+                    name: String::from("Mem"),
+                    active: true,
+                    expanded: true,
+                },
+                StreamConfig {
+                    name: String::from("CPU"),
+                    active: false,
+                    expanded: false,
+                },
+            ],
+        };
+
+        let string = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&string).unwrap(), config);
+    }
+
+    #[test]
+    fn test_config_save() {
+        let directory = env::temp_dir().join(format!("hegemon-test-config-{}", process::id()));
+        let path = directory.join("config.toml");
+
+        let config = Config {
+            interval: Some(2_000),
+            ..Config::default()
+        };
+        config.save(&path).unwrap();
+        // Saving replaces the existing file
+        Config::default().save(&path).unwrap();
+
+        let loaded = Config::load(&path);
+        let files = fs::read_dir(&directory).unwrap().count();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(loaded.unwrap(), Config::default());
+        // No temporary files are left behind
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: Config = toml::from_str("[[streams]]\nname = \"CPU\"\n").unwrap();
+        assert_eq!(config.interval, None);
+        assert_eq!(config.timeout, None);
+        assert_eq!(config.exclude_virtual_interfaces, None);
+        assert_eq!(config.streams.len(), 1);
+        assert!(config.streams[0].active);
+        assert!(!config.streams[0].expanded);
+    }
+}
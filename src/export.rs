@@ -0,0 +1,153 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::iter;
+
+/// Text format for exporting stream values without the user interface.
+pub enum Format {
+    /// Comma-separated values, with a header line containing the stream names
+    Csv,
+    /// One JSON object per line, mapping stream names to values
+    JsonLines,
+    /// InfluxDB line protocol, with one line per update and one field per stream
+    Influx,
+}
+
+impl Format {
+    /// Returns the format with the given name, or `None` if no such format exists.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::JsonLines),
+            "influx" => Some(Format::Influx),
+            _ => None,
+        }
+    }
+
+    /// Returns the line to be printed before any values, if the format has one.
+    pub fn header(&self, names: &[String]) -> Option<String> {
+        match self {
+            Format::Csv => Some(csv_line(
+                iter::once(String::from("time")).chain(names.iter().map(|n| csv_escape(n))),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns the line representing the given values, recorded at the given time
+    /// (in milliseconds since the Unix epoch), or `None` if there is nothing to print.
+    pub fn record(&self, time: u64, values: &[(String, Option<f64>)]) -> Option<String> {
+        match self {
+            Format::Csv => Some(csv_line(
+                iter::once(time.to_string()).chain(
+                    values
+                        .iter()
+                        .map(|(_, value)| value.map_or_else(String::new, |v| v.to_string())),
+                ),
+            )),
+            Format::JsonLines => Some(format!(
+                "{{\"time\":{}{}}}",
+                time,
+                values
+                    .iter()
+                    .map(|(name, value)| format!(
+                        ",{}:{}",
+                        json_escape(name),
+                        value.map_or_else(|| String::from("null"), |v| v.to_string()),
+                    ))
+                    .collect::<String>(),
+            )),
+            Format::Influx => {
+                let fields = values
+                    .iter()
+                    .filter_map(|(name, value)| value.map(|v| format!("{}={}", influx_escape(name), v)))
+                    .collect::<Vec<_>>();
+
+                // Points without fields are invalid
+                if fields.is_empty() {
+                    None
+                } else {
+                    // Timestamps have nanosecond precision by default
+                    Some(format!("hegemon {} {}", fields.join(","), time * 1_000_000))
+                }
+            }
+        }
+    }
+}
+
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    fields.collect::<Vec<_>>().join(",")
+}
+
+fn csv_escape(string: &str) -> String {
+    if string.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", string.replace('"', "\"\""))
+    } else {
+        String::from(string)
+    }
+}
+
+fn json_escape(string: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+fn influx_escape(string: &str) -> String {
+    string.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let names = vec![String::from("CPU"), String::from("A, \"B\"")];
+        let values = vec![(names[0].clone(), Some(12.5)), (names[1].clone(), None)];
+
+        assert_eq!(Format::Csv.header(&names).unwrap(), "time,CPU,\"A, \"\"B\"\"\"");
+        assert_eq!(Format::Csv.record(1_000, &values).unwrap(), "1000,12.5,");
+
+        assert!(Format::JsonLines.header(&names).is_none());
+        assert_eq!(
+            Format::JsonLines.record(1_000, &values).unwrap(),
+            "{\"time\":1000,\"CPU\":12.5,\"A, \\\"B\\\"\":null}",
+        );
+
+        assert_eq!(
+            Format::Influx.record(1_000, &values).unwrap(),
+            "hegemon CPU=12.5 1000000000",
+        );
+        assert_eq!(
+            Format::Influx
+                .record(1_000, &[(String::from("A=B C"), Some(1.0))])
+                .unwrap(),
+            "hegemon A\\=B\\ C=1 1000000000",
+        );
+        assert!(Format::Influx.record(1_000, &values[1..]).is_none());
+    }
+}
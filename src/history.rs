@@ -0,0 +1,188 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+
+/// Summary of all values recorded during a time period.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bucket {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: usize,
+}
+
+impl Bucket {
+    fn new(value: f64) -> Self {
+        Bucket {
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn avg(&self) -> f64 {
+        self.sum / (self.count as f64)
+    }
+}
+
+// Values rolled up into buckets covering `resolution` milliseconds each.
+// Only buckets that contain values are stored, along with their indices
+// (the bucket with index `i` covers the time from `i * resolution` to `(i + 1) * resolution`).
+struct Tier {
+    resolution: u64,
+    buckets: VecDeque<(u64, Bucket)>,
+}
+
+impl Tier {
+    fn push(&mut self, time: u64, value: f64, capacity: usize) {
+        let index = time / self.resolution;
+
+        match self.buckets.back_mut() {
+            Some((last_index, bucket)) if *last_index == index => bucket.add(value),
+            _ => self.buckets.push_back((index, Bucket::new(value))),
+        }
+
+        while let Some(&(first_index, _)) = self.buckets.front() {
+            if first_index + (capacity as u64) <= index {
+                self.buckets.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// The value history of a data stream, stored at multiple resolutions
+/// so that it can be displayed at any update interval.
+pub struct History {
+    tiers: Vec<Tier>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates a history with one tier for each of the given resolutions (in milliseconds),
+    /// each storing values spanning `capacity` buckets.
+    pub fn new(resolutions: &[u64], capacity: usize) -> Self {
+        History {
+            tiers: resolutions
+                .iter()
+                .map(|&resolution| Tier {
+                    resolution,
+                    buckets: VecDeque::new(),
+                })
+                .collect(),
+            capacity,
+        }
+    }
+
+    /// Discards all recorded values.
+    pub fn clear(&mut self) {
+        for tier in &mut self.tiers {
+            tier.buckets.clear();
+        }
+    }
+
+    /// Records a value at the given time (in milliseconds).
+    /// Times must not decrease between calls.
+    pub fn push(&mut self, time: u64, value: f64) {
+        for tier in &mut self.tiers {
+            tier.push(time, value, self.capacity);
+        }
+    }
+
+    /// Like `push`, but only records the value at the given resolution (in milliseconds).
+    pub fn push_at(&mut self, resolution: u64, time: u64, value: f64) {
+        if let Some(tier) = self.tiers.iter_mut().find(|t| t.resolution == resolution) {
+            tier.push(time, value, self.capacity);
+        }
+    }
+
+    /// Returns `count` consecutive buckets of the given resolution (in milliseconds),
+    /// the last of which contains the given time, in chronological order.
+    /// Periods for which no values have been recorded are `None`.
+    pub fn buckets(&self, resolution: u64, time: u64, count: usize) -> Vec<Option<Bucket>> {
+        let mut buckets = vec![None; count];
+
+        let tier = match self.tiers.iter().find(|t| t.resolution == resolution) {
+            Some(tier) => tier,
+            None => return buckets,
+        };
+
+        let last_index = time / resolution;
+
+        for &(index, bucket) in tier.buckets.iter().rev() {
+            if index > last_index {
+                continue;
+            }
+
+            let offset = (last_index - index) as usize;
+            if offset >= count {
+                break;
+            }
+
+            buckets[count - 1 - offset] = Some(bucket);
+        }
+
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn averages(buckets: Vec<Option<Bucket>>) -> Vec<Option<f64>> {
+        buckets.iter().map(|b| b.map(|b| b.avg())).collect()
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(&[1_000, 3_000], 4);
+
+        for (time, value) in &[(0, 1.0), (1_000, 2.0), (2_000, 3.0), (4_000, 5.0), (5_000, 6.0)] {
+            history.push(*time, *value);
+        }
+
+        assert_eq!(
+            averages(history.buckets(1_000, 5_000, 6)),
+            vec![None, None, Some(3.0), None, Some(5.0), Some(6.0)]
+        );
+        assert_eq!(
+            averages(history.buckets(3_000, 5_000, 3)),
+            vec![None, Some(2.0), Some(5.5)]
+        );
+        // Older buckets have been discarded
+        assert_eq!(averages(history.buckets(1_000, 2_000, 2)), vec![None, Some(3.0)]);
+        assert_eq!(averages(history.buckets(2_000, 5_000, 1)), vec![None]);
+
+        let bucket = history.buckets(3_000, 0, 1)[0].unwrap();
+        assert_eq!((bucket.min, bucket.max, bucket.count), (1.0, 3.0, 3));
+
+        // Values pushed at one resolution don't affect the others
+        history.push_at(3_000, 6_000, 7.0);
+        assert_eq!(averages(history.buckets(3_000, 6_000, 1)), vec![Some(7.0)]);
+        assert_eq!(averages(history.buckets(1_000, 6_000, 1)), vec![None]);
+    }
+}
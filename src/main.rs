@@ -20,49 +20,393 @@ extern crate clap;
 extern crate crossbeam_channel;
 extern crate regex;
 extern crate sensors;
+extern crate serde;
 extern crate signal_hook;
 extern crate systemstat;
 extern crate termion;
+extern crate toml;
 
+mod config;
+mod export;
+mod history;
 mod model;
+mod processes;
+mod prometheus;
 mod providers;
+mod recording;
+mod sampler;
 mod stream;
 mod terminal;
 mod theme;
 mod view;
 
-use crate::model::Application;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+
+use clap::{Arg, Error, ErrorKind};
+use crossbeam_channel::Receiver;
+
+use crate::config::Config;
+use crate::export::Format;
+use crate::model::{Application, Interval, Screen};
+use crate::prometheus::MetricsServer;
+use crate::recording::{Recorder, Recording};
+use crate::stream::Stream;
 use crate::terminal::Terminal;
 use crate::theme::Theme;
+use crate::view::{format_duration, parse_duration};
 
 fn main() {
-    let _matches = app_from_crate!().get_matches();
+    let matches = app_from_crate!()
+        .arg(
+            Arg::with_name("interval")
+                .short("i")
+                .long("interval")
+                .value_name("DURATION")
+                .help("Sets the initial update interval (e.g. 500ms, 2s, 1m)")
+                .validator(|value| match parse_duration(&value) {
+                    Some(duration) if Interval::all().iter().any(|i| i.duration == duration) => Ok(()),
+                    _ => Err(format!(
+                        "Interval must be one of: {}",
+                        Interval::all()
+                            .iter()
+                            .map(|i| format_duration(i.duration, "", ""))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    )),
+                }),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .help("Sets how long to wait for slow streams before skipping their values [default: 500ms]")
+                .validator(|value| match parse_duration(&value) {
+                    Some(_) => Ok(()),
+                    None => Err(String::from("Invalid duration")),
+                }),
+        )
+        .arg(
+            Arg::with_name("streams")
+                .short("s")
+                .long("streams")
+                .value_name("PATTERNS")
+                .help("Shows only streams matching the given comma-separated glob patterns, in that order")
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("pid")
+                .long("pid")
+                .value_name("PIDS")
+                .help("Shows streams for the processes with the given comma-separated PIDs")
+                .use_delimiter(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(String::from("Invalid PID")),
+                }),
+        )
+        .arg(
+            Arg::with_name("process-name")
+                .long("process-name")
+                .value_name("NAME")
+                .help("Shows streams for all processes with the given name (as shown on the processes screen)"),
+        )
+        .arg(
+            Arg::with_name("exclude-virtual-interfaces")
+                .long("exclude-virtual-interfaces")
+                .help("Hides network streams for the loopback interface and container interfaces (docker*, veth*), like the exclude_virtual_interfaces setting in the configuration file"),
+        )
+        .arg(
+            Arg::with_name("expand")
+                .short("e")
+                .long("expand")
+                .help("Expands all shown streams"),
+        )
+        .arg(
+            Arg::with_name("theme")
+                .short("t")
+                .long("theme")
+                .value_name("THEME")
+                .help("Sets the color theme")
+                .possible_values(&["default", "light"]),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Reads and writes settings from/to the given file instead of the default location"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Records all values to the given file, appending to any existing recording"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Shows the values from the given recording instead of live values")
+                .conflicts_with_all(&["record", "pid", "process-name"]),
+        )
+        .arg(
+            Arg::with_name("export")
+                .short("x")
+                .long("export")
+                .value_name("FORMAT")
+                .help("Prints values to standard output in the given format instead of showing the interface")
+                .possible_values(&["csv", "json", "influx"])
+                .conflicts_with("replay"),
+        )
+        .arg(
+            Arg::with_name("prometheus")
+                .short("p")
+                .long("prometheus")
+                .value_name("ADDRESS")
+                .help("Serves the values of all streams, including hidden ones, as Prometheus metrics at http://ADDRESS/metrics (e.g. 127.0.0.1:9101)")
+                .conflicts_with("replay"),
+        )
+        .arg(
+            Arg::with_name("daemon")
+                .short("d")
+                .long("daemon")
+                .help("Only serves Prometheus metrics instead of showing the interface")
+                .requires("prometheus")
+                .conflicts_with("export"),
+        )
+        .arg(
+            Arg::with_name("list-streams")
+                .short("l")
+                .long("list-streams")
+                .help("Prints the names and descriptions of all available streams and exits"),
+        )
+        .get_matches();
+
+    // During replay, the streams are those from the recording,
+    // whose settings must neither be affected by nor affect those of live streams
+    let config_path = match matches.value_of_os("config") {
+        _ if matches.is_present("replay") => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => Config::default_path(),
+    };
+
+    // Settings given on the command line only apply to the current session,
+    // and must not overwrite those stored in the configuration file
+    let save_config = ![
+        "interval",
+        "timeout",
+        "streams",
+        "expand",
+        "pid",
+        "process-name",
+        "exclude-virtual-interfaces",
+    ]
+    .iter()
+    .any(|&name| matches.is_present(name));
+
+    // The configuration must be loaded before the terminal is set up,
+    // so that errors can be reported in the normal way, and before
+    // the streams are created, because it determines which ones are
+    let config = match config_path {
+        Some(ref path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!("Error loading configuration file {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        },
+        None => Config::default(),
+    };
+
+    let exclude_virtual_interfaces =
+        matches.is_present("exclude-virtual-interfaces") || config.exclude_virtual_interfaces == Some(true);
+
+    let mut streams = providers::streams(exclude_virtual_interfaces);
+
+    if matches.is_present("list-streams") {
+        let name_width = streams.iter().map(|s| s.name().chars().count()).max().unwrap_or(0);
+        for stream in &streams {
+            println!("{:width$}  {}", stream.name(), stream.description(), width = name_width);
+        }
+        return;
+    }
+
+    let recording = matches.value_of_os("replay").map(|path| {
+        let path = PathBuf::from(path);
+        match Recording::load(&path) {
+            Ok(recording) => recording,
+            Err(error) => {
+                eprintln!("Error loading recording {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        }
+    });
+
+    if let Some(ref recording) = recording {
+        streams = recording.streams(streams);
+    }
+
+    let recorder = matches.value_of_os("record").map(|path| {
+        let path = PathBuf::from(path);
+        match Recorder::open(&path) {
+            Ok(recorder) => recorder,
+            Err(error) => {
+                eprintln!("Error opening recording {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        }
+    });
+
+    let metrics_server = matches
+        .value_of("prometheus")
+        .map(|address| match MetricsServer::start(address) {
+            Ok(mut metrics_server) => {
+                // Metric names are assigned in the order of the providers,
+                // before the streams are reordered by the configuration
+                metrics_server.add_streams(&streams.iter().map(|s| &**s).collect::<Vec<_>>());
+                metrics_server
+            }
+            Err(error) => {
+                eprintln!("Error serving metrics at {}: {}", address, error);
+                process::exit(1);
+            }
+        });
+
+    // Standard output might not be a terminal without the interface,
+    // in which case the size doesn't matter
+    let (width, height) = if matches.is_present("export") || matches.is_present("daemon") {
+        (80, 24)
+    } else {
+        Terminal::size()
+    };
+
+    let mut application = Application::new(width, height, streams);
+    application.apply_config(&config);
+
+    // Command line arguments take precedence over the configuration file
+    if let Some(interval) = matches.value_of("interval") {
+        application.set_interval(parse_duration(interval).unwrap());
+    }
+
+    if let Some(timeout) = matches.value_of("timeout") {
+        application.timeout = parse_duration(timeout).unwrap();
+    }
+
+    if let Some(patterns) = matches.values_of("streams") {
+        if !application.select_streams(&patterns.collect::<Vec<_>>()) {
+            Error::with_description("No streams match the given patterns", ErrorKind::ValueValidation).exit();
+        }
+    }
+
+    let mut pids = match matches.values_of("pid") {
+        Some(pids) => pids.map(|pid| pid.parse::<u32>().unwrap()).collect(),
+        None => Vec::new(),
+    };
+
+    if let Some(name) = matches.value_of("process-name") {
+        let named_pids = processes::pids_named(name);
+        if named_pids.is_empty() {
+            Error::with_description("No process has the given name", ErrorKind::ValueValidation).exit();
+        }
+        pids.extend(named_pids);
+    }
+
+    // Process streams are added after selecting streams, so they are always shown
+    for pid in pids {
+        let process_streams = providers::process_streams(pid);
+        if process_streams.is_empty() {
+            Error::with_description(&format!("No process has the PID {}", pid), ErrorKind::ValueValidation).exit();
+        }
+        application.add_streams(process_streams);
+    }
+
+    if matches.is_present("expand") {
+        for stream in &mut application.streams {
+            if stream.active {
+                stream.expanded = true;
+            }
+        }
+    }
+
+    if let Some(recording) = recording {
+        application.replay(recording);
+    }
+
+    if let Some(recorder) = recorder {
+        application.record(recorder);
+    }
+
+    if let Some(metrics_server) = metrics_server {
+        application.serve_metrics(metrics_server);
+    }
+
+    if matches.is_present("daemon") {
+        let update = crossbeam_channel::tick(application.update_period());
+
+        // Runs until the process is terminated
+        loop {
+            application.update_streams();
+
+            if let Some(ref error) = application.recording_error {
+                eprintln!("Error writing recording: {}", error);
+                process::exit(1);
+            }
+
+            update.recv().unwrap();
+        }
+    }
+
+    if let Some(format) = matches.value_of("export") {
+        export(&mut application, &Format::named(format).unwrap());
+
+        if let Some(ref error) = application.recording_error {
+            eprintln!("Error writing recording: {}", error);
+            process::exit(1);
+        }
+
+        return;
+    }
 
     let terminal = Terminal::new();
-    let (width, height) = terminal.size();
 
-    let mut application = Application::new(width, height, providers::streams());
-    application.update_streams();
+    // Values are received asynchronously, so that the interface
+    // remains responsive while waiting for slow streams
+    let samples = application.samples();
 
-    let theme = Theme::default();
+    application.start_update();
+    let mut update_deadline = deadline(&application);
+
+    let theme = Theme::named(matches.value_of("theme").unwrap_or("default")).unwrap();
     terminal.print(application.render(&theme));
 
-    let mut update = crossbeam_channel::tick(application.interval().duration);
+    let mut update = crossbeam_channel::tick(application.update_period());
 
     // Main event loop
     loop {
         select! {
             recv(terminal.input) -> event => {
                 let interval_index = application.interval_index;
+                let streams_screen = application.screen == Screen::Streams;
 
                 if application.handle(&event.unwrap()) {
                     if !application.running {
                         break;
                     }
+                    if save_config && streams_screen && application.screen != Screen::Streams {
+                        if let Some(ref path) = config_path {
+                            // Errors cannot be reported while the interface is running,
+                            // and saving will be retried on exit
+                            application.config().save(path).ok();
+                        }
+                    }
                     if application.interval_index != interval_index {
                         application.reset_streams();
-                        application.update_streams();
-                        update = crossbeam_channel::tick(application.interval().duration);
+                        application.start_update();
+                        update_deadline = deadline(&application);
+                        update = crossbeam_channel::tick(application.update_period());
                     }
                     terminal.print(application.render(&theme));
                 } else {
@@ -71,7 +415,7 @@ fn main() {
                 }
             },
             recv(terminal.resize) -> _ => {
-                let (width, height) = terminal.size();
+                let (width, height) = Terminal::size();
                 application.resize(width, height);
                 terminal.print(application.render(&theme));
             },
@@ -79,9 +423,85 @@ fn main() {
                 break;
             },
             recv(update) -> _ => {
-                application.update_streams();
+                application.start_update();
+                update_deadline = deadline(&application);
+                terminal.print(application.render(&theme));
+            },
+            recv(samples) -> sample => {
+                if application.receive_sample(sample.unwrap()) {
+                    update_deadline = crossbeam_channel::never();
+                    terminal.print(application.render(&theme));
+                }
+            },
+            recv(update_deadline) -> _ => {
+                application.finish_update();
+                update_deadline = crossbeam_channel::never();
                 terminal.print(application.render(&theme));
             },
         }
     }
+
+    // Restore the terminal before reporting any errors
+    drop(terminal);
+
+    if let (true, Some(ref path)) = (save_config, &config_path) {
+        if let Err(error) = application.config().save(path) {
+            eprintln!("Error saving configuration file {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    if let Some(ref error) = application.recording_error {
+        eprintln!("Error writing recording: {}", error);
+        process::exit(1);
+    }
+}
+
+// Returns a channel that delivers a message when the update in progress
+// should be completed, or never if no update is in progress
+fn deadline(application: &Application) -> Receiver<Instant> {
+    match application.update_deadline() {
+        Some(deadline) => crossbeam_channel::after(deadline.saturating_duration_since(Instant::now())),
+        None => crossbeam_channel::never(),
+    }
+}
+
+// Prints the values of all active streams after each update,
+// until standard output is closed
+fn export(application: &mut Application, format: &Format) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    let names = application
+        .active_streams()
+        .iter()
+        .map(|s| s.stream.name())
+        .collect::<Vec<_>>();
+
+    if let Some(header) = format.header(&names) {
+        if writeln!(stdout, "{}", header).is_err() {
+            return;
+        }
+    }
+
+    let update = crossbeam_channel::tick(application.update_period());
+
+    loop {
+        application.update_streams();
+
+        let values = application
+            .active_streams()
+            .iter()
+            .map(|s| (s.stream.name(), s.last_value))
+            .collect::<Vec<_>>();
+
+        if let Some(line) = format.record(application.timestamp(), &values) {
+            // Lines must be written immediately when piping into other programs
+            if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+                return;
+            }
+        }
+
+        update.recv().unwrap();
+    }
 }
@@ -14,14 +14,34 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::{HashMap, VecDeque};
-use std::time::Duration;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use crossbeam_channel::Receiver;
+use regex::{self, Regex};
 use termion::event::{Event, Key, MouseButton, MouseEvent};
 
+use crate::config::{Config, StreamConfig};
+use crate::history::History;
+use crate::processes::{ProcessTable, SortOrder};
+use crate::prometheus::MetricsServer;
+use crate::providers;
+use crate::recording::{Recorder, Recording};
+use crate::sampler::{Sample, Sampler, SharedStream};
 use crate::stream::Stream;
 
-const VALUE_HISTORY_SIZE: usize = 512;
+// Number of buckets stored at each resolution,
+// which is the maximum number of values that can be displayed
+const HISTORY_SIZE: usize = 1024;
+
+// Updates happen at this rate during replay, independently of the interval,
+// which determines how much recorded time passes with each update
+const REPLAY_UPDATE_PERIOD: Duration = Duration::from_millis(100);
+
+// In milliseconds
+const DEFAULT_TIMEOUT: u64 = 500;
 
 pub struct Application {
     pub running: bool,
@@ -32,8 +52,47 @@ pub struct Application {
     pub selection_index: usize,
     pub scroll_index: usize,
     pub scroll_anchor: ScrollAnchor,
+    // Selection, scrolling and search state of the streams screen.
+    // The selection index refers to the list of *filtered* streams.
+    pub streams_selection_index: usize,
+    pub streams_scroll_index: usize,
+    pub streams_filter: String,
+    // Selection and scrolling state of the processes screen,
+    // whose table is only updated while the screen is shown.
+    // The selection follows the selected process when the table is
+    // updated or sorted, so the selection index is derived from its PID.
+    pub processes: ProcessTable,
+    pub processes_selection_index: usize,
+    processes_selection_pid: Option<u32>,
+    pub processes_scroll_index: usize,
     intervals: Vec<Interval>,
     pub interval_index: usize,
+    /// Maximum time to wait for streams to provide their values
+    pub timeout: Duration,
+    // Only takes effect when the streams are created, before the application is,
+    // so the setting is merely carried over from the loaded configuration to the saved one
+    exclude_virtual_interfaces: Option<bool>,
+    // Settings of configured streams that don't exist in this session (e.g. because
+    // a device is unplugged), together with the name of the existing stream they
+    // followed in the configuration, so that they are kept when the configuration is saved
+    missing_stream_configs: Vec<(Option<String>, StreamConfig)>,
+    sampler: Sampler,
+    // Time and names of the streams being sampled by the update in progress
+    pending_update: Option<(u64, Vec<String>)>,
+    // Time at which the last values were recorded, in milliseconds since `start`
+    // (or since the first recorded value during replay)
+    time: Option<u64>,
+    // Interval (in milliseconds) with which `time` was last advanced
+    time_interval: u64,
+    start: Instant,
+    // `start` in milliseconds since the Unix epoch
+    // (or the time of the first recorded value during replay)
+    start_timestamp: u64,
+    recorder: Option<Recorder>,
+    /// The error that caused recording to stop, if any
+    pub recording_error: Option<io::Error>,
+    pub replay: Option<Replay>,
+    metrics_server: Option<MetricsServer>,
     // The two parts of the map value contain
     // the left/right-aligned menu items, respectively
     menus: HashMap<Screen, (Vec<MenuItem>, Vec<MenuItem>)>,
@@ -50,6 +109,7 @@ impl Application {
                     MenuItem::new("\u{1F805}\u{1F807}", "Select"),
                     MenuItem::new("Space", "Expand"),
                     MenuItem::new("S", "Streams"),
+                    MenuItem::new("T", "Processes"),
                     MenuItem::new("+-", "Interval"),
                 ],
                 vec![MenuItem::new("Q", "Quit")],
@@ -63,35 +123,211 @@ impl Application {
                     MenuItem::new("\u{1F805}\u{1F807}", "Select"),
                     MenuItem::new("Space", "Toggle"),
                     MenuItem::new("+-", "Reorder"),
+                    MenuItem::new("A-Z", "Search"),
+                ],
+                vec![MenuItem::new("Esc", "Done")],
+            ),
+        );
+
+        menus.insert(
+            Screen::Processes,
+            (
+                vec![
+                    MenuItem::new("\u{1F805}\u{1F807}", "Select"),
+                    MenuItem::new("Space", "Pin"),
+                    MenuItem::new("C", "Sort by CPU"),
+                    MenuItem::new("M", "Sort by memory"),
                 ],
                 vec![MenuItem::new("Esc", "Done")],
             ),
         );
 
-        Application {
+        let intervals = Interval::all();
+        let resolutions = resolutions(&intervals);
+
+        let mut application = Application {
             running: true,
             width,
             height,
             screen: Screen::Main,
-            streams: streams.into_iter().map(StreamWrapper::new).collect(),
+            streams: streams
+                .into_iter()
+                .map(|s| StreamWrapper::new(s, &resolutions))
+                .collect(),
             selection_index: 0,
             scroll_index: 0,
             scroll_anchor: ScrollAnchor::Top,
-            intervals: vec![
-                Interval::new(100, 10),
-                Interval::new(200, 10),
-                Interval::new(500, 10),
-                Interval::new(1_000, 10),
-                Interval::new(2_000, 15),
-                Interval::new(3_000, 10),
-                Interval::new(5_000, 12),
-                Interval::new(10_000, 12),
-                Interval::new(30_000, 10),
-                Interval::new(60_000, 10),
-                Interval::new(300_000, 12),
-            ],
+            streams_selection_index: 0,
+            streams_scroll_index: 0,
+            streams_filter: String::new(),
+            processes: ProcessTable::new(),
+            processes_selection_index: 0,
+            processes_selection_pid: None,
+            processes_scroll_index: 0,
+            intervals,
             interval_index: 3,
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT),
+            exclude_virtual_interfaces: None,
+            missing_stream_configs: Vec::new(),
+            sampler: Sampler::new(),
+            pending_update: None,
+            time: None,
+            time_interval: 0,
+            start: Instant::now(),
+            start_timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            recorder: None,
+            recording_error: None,
+            replay: None,
+            metrics_server: None,
             menus,
+        };
+
+        // At least one stream must be active at all times
+        if !application.streams.is_empty() && application.active_streams().is_empty() {
+            application.streams[0].active = true;
+        }
+
+        application
+    }
+
+    /// Applies the settings from the given configuration.
+    /// Streams are matched by name, and names that don't correspond
+    /// to any existing stream are ignored, but retained in the configuration
+    /// returned by `config`. Streams not mentioned
+    /// in the configuration (e.g. from newly added providers) are placed
+    /// after the configured stream that precedes them by default,
+    /// so that they appear next to related streams.
+    pub fn apply_config(&mut self, config: &Config) {
+        if let Some(milliseconds) = config.interval {
+            self.set_interval(Duration::from_millis(milliseconds));
+        }
+
+        if let Some(milliseconds) = config.timeout {
+            self.timeout = Duration::from_millis(milliseconds);
+        }
+
+        self.exclude_virtual_interfaces = config.exclude_virtual_interfaces;
+
+        let positions = config
+            .streams
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.name.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let names = self.streams.iter().map(|s| s.stream.name()).collect::<HashSet<_>>();
+
+        let mut previous_name = None;
+
+        self.missing_stream_configs = config
+            .streams
+            .iter()
+            .filter_map(|stream_config| {
+                if names.contains(&stream_config.name) {
+                    previous_name = Some(stream_config.name.clone());
+                    None
+                } else {
+                    Some((previous_name.clone(), stream_config.clone()))
+                }
+            })
+            .collect();
+
+        let mut previous_position = None;
+
+        let mut keyed_streams = self
+            .streams
+            .drain(..)
+            .enumerate()
+            .map(|(i, stream)| {
+                let key = match positions.get(&stream.stream.name()) {
+                    Some(&position) => {
+                        previous_position = Some(position);
+                        (Some(position), false, i)
+                    }
+                    None => (previous_position, true, i),
+                };
+                (key, stream)
+            })
+            .collect::<Vec<_>>();
+
+        keyed_streams.sort_by_key(|&(key, _)| key);
+
+        self.streams = keyed_streams.into_iter().map(|(_, stream)| stream).collect();
+
+        for stream in &mut self.streams {
+            if let Some(&position) = positions.get(&stream.stream.name()) {
+                stream.active = config.streams[position].active;
+                stream.expanded = config.streams[position].expanded;
+            }
+        }
+
+        // At least one stream must be active at all times
+        if !self.streams.is_empty() && self.active_streams().is_empty() {
+            self.streams[0].active = true;
+        }
+    }
+
+    /// Activates exactly those streams whose names match at least one
+    /// of the given glob patterns, and orders them by the first pattern
+    /// they match. Returns `false` (without changing anything)
+    /// if no stream matches any of the patterns.
+    pub fn select_streams(&mut self, patterns: &[&str]) -> bool {
+        let regexes = patterns.iter().map(|p| glob_regex(p)).collect::<Vec<_>>();
+
+        let pattern_index = |stream: &StreamWrapper| {
+            let name = stream.stream.name();
+            regexes.iter().position(|r| r.is_match(&name))
+        };
+
+        if !self.streams.iter().any(|s| pattern_index(s).is_some()) {
+            return false;
+        }
+
+        self.streams.sort_by_key(|s| pattern_index(s).unwrap_or(usize::MAX));
+
+        for stream in &mut self.streams {
+            stream.active = pattern_index(stream).is_some();
+        }
+
+        true
+    }
+
+    /// Returns the current settings as a configuration object.
+    /// Settings of configured streams that don't exist in this session
+    /// are retained after the streams they followed.
+    pub fn config(&self) -> Config {
+        let names = self.streams.iter().map(|s| s.stream.name()).collect::<HashSet<_>>();
+
+        let missing_stream_configs = |previous_name: Option<&str>| {
+            self.missing_stream_configs
+                .iter()
+                .filter(|(name, stream_config)| {
+                    name.as_deref() == previous_name && !names.contains(&stream_config.name)
+                })
+                .map(|(_, stream_config)| stream_config.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut streams = missing_stream_configs(None);
+
+        for stream in &self.streams {
+            let name = stream.stream.name();
+            streams.push(StreamConfig {
+                name: name.clone(),
+                active: stream.active,
+                expanded: stream.expanded,
+            });
+            streams.extend(missing_stream_configs(Some(&name)));
+        }
+
+        Config {
+            interval: Some(self.interval().duration.as_millis() as u64),
+            timeout: Some(self.timeout.as_millis() as u64),
+            exclude_virtual_interfaces: self.exclude_virtual_interfaces,
+            streams,
         }
     }
 
@@ -99,6 +335,172 @@ impl Application {
         self.intervals[self.interval_index]
     }
 
+    /// Returns the time at which the last values were recorded,
+    /// in milliseconds since the application was started
+    /// (or since the first recorded value during replay).
+    pub fn time(&self) -> u64 {
+        self.time.unwrap_or(0)
+    }
+
+    /// Returns the time at which the last values were recorded,
+    /// in milliseconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.start_timestamp + self.time()
+    }
+
+    /// Returns the time between updates.
+    pub fn update_period(&self) -> Duration {
+        if self.replay.is_some() {
+            REPLAY_UPDATE_PERIOD
+        } else {
+            self.interval().duration
+        }
+    }
+
+    /// Records all values from now on using the given recorder.
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Serves all values from now on using the given metrics server.
+    pub fn serve_metrics(&mut self, mut metrics_server: MetricsServer) {
+        // Streams the server doesn't know about yet were added after those from the providers
+        // (e.g. process streams), and are still in the order in which they were added
+        metrics_server.add_streams(
+            &self
+                .streams
+                .iter()
+                .map(|s| &s.stream as &dyn Stream)
+                .collect::<Vec<_>>(),
+        );
+        self.metrics_server = Some(metrics_server);
+    }
+
+    /// Replaces live values with those from the given recording.
+    /// Replay starts paused at the end of the recording.
+    pub fn replay(&mut self, recording: Recording) {
+        let end = match (recording.samples.first(), recording.samples.last()) {
+            (Some(first), Some(last)) => {
+                self.start_timestamp = first.0;
+                last.0 - first.0
+            }
+            _ => 0,
+        };
+
+        self.replay = Some(Replay {
+            recording,
+            position: 0,
+            resolution: None,
+            paused: true,
+        });
+
+        self.seek(end);
+
+        if let Some((left_menu, _)) = self.menus.get_mut(&Screen::Main) {
+            // The interval item must remain last, because the interval is displayed next to it
+            let index = left_menu.len() - 1;
+            left_menu.insert(index, MenuItem::new("P", "Pause"));
+            left_menu.insert(index, MenuItem::new("\u{1F804}\u{1F806}", "Scrub"));
+        }
+    }
+
+    // Moves the replay to the given time, relative to the first recorded value
+    fn seek(&mut self, time: u64) {
+        let current_time = self.time();
+        let resolution = self.interval().duration.as_millis() as u64;
+
+        let replay = match self.replay {
+            Some(ref mut replay) => replay,
+            None => return,
+        };
+
+        let samples = &replay.recording.samples;
+
+        let start = samples.first().map_or(0, |s| s.0);
+        let end = samples.last().map_or(0, |s| s.0) - start;
+        let time = min(time, end);
+
+        if time < current_time || replay.resolution.is_some_and(|r| r != resolution) {
+            // History can only move forward, so it must be rebuilt. Rebuilding it
+            // from the beginning would make scrubbing long recordings very slow,
+            // so only the values that can be displayed at the current interval
+            // are added, and the history is rebuilt again if the interval changes.
+            for stream in &mut self.streams {
+                stream.history.clear();
+            }
+            let window_start = ((time / resolution).saturating_sub(HISTORY_SIZE as u64)) * resolution;
+            replay.position = samples.partition_point(|s| s.0 - start < window_start);
+            replay.resolution = Some(resolution);
+        }
+
+        let indices = self
+            .streams
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.stream.name(), i))
+            .collect::<HashMap<_, _>>();
+
+        let stream_indices = replay
+            .recording
+            .streams
+            .iter()
+            .map(|s| indices.get(&s.name).cloned())
+            .collect::<Vec<_>>();
+
+        while replay.position < samples.len() && samples[replay.position].0 - start <= time {
+            let (sample_time, index, value) = samples[replay.position];
+            if let Some(i) = stream_indices[index] {
+                self.streams[i].push_value(sample_time - start, Some(value), replay.resolution);
+            }
+            replay.position += 1;
+        }
+
+        if time == end {
+            replay.paused = true;
+        }
+
+        self.time = Some(time);
+    }
+
+    /// Adds the given streams (e.g. for a process) after all existing streams
+    /// and activates them. If a stream with the same name already exists,
+    /// that stream is activated instead.
+    pub fn add_streams(&mut self, streams: Vec<Box<dyn Stream>>) {
+        let resolutions = resolutions(&self.intervals);
+
+        for stream in streams {
+            match self.streams.iter_mut().find(|s| s.stream.name() == stream.name()) {
+                Some(existing_stream) => {
+                    if !existing_stream.active {
+                        existing_stream.active = true;
+                        existing_stream.stream.reset();
+                    }
+                }
+                None => {
+                    if let Some(ref mut metrics_server) = self.metrics_server {
+                        metrics_server.add_streams(&[&*stream]);
+                    }
+
+                    let mut stream = StreamWrapper::new(stream, &resolutions);
+                    stream.active = true;
+                    self.streams.push(stream);
+                }
+            }
+        }
+    }
+
+    /// Sets the update interval to the given duration.
+    /// Returns `false` if the duration is not one of the available intervals.
+    pub fn set_interval(&mut self, duration: Duration) -> bool {
+        match self.intervals.iter().position(|i| i.duration == duration) {
+            Some(index) => {
+                self.interval_index = index;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn menu(&self) -> (Vec<MenuItem>, Vec<MenuItem>) {
         self.menus[&self.screen].clone()
     }
@@ -107,6 +509,19 @@ impl Application {
         self.streams.iter().filter(|s| s.active).collect()
     }
 
+    /// Returns the indices (into `streams`) of all streams
+    /// whose names match the streams screen's search filter.
+    pub fn filtered_stream_indices(&self) -> Vec<usize> {
+        let filter = self.streams_filter.to_lowercase();
+
+        self.streams
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.stream.name().to_lowercase().contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
     pub fn handle(&mut self, event: &Event) -> bool {
         match self.screen {
             Screen::Main => match event {
@@ -140,6 +555,29 @@ impl Application {
                         self.screen = Screen::Streams;
                         return true;
                     }
+                    Key::Char('t') => {
+                        self.screen = Screen::Processes;
+                        // CPU usage is only known from the second update on
+                        self.update_processes();
+                        return true;
+                    }
+                    Key::Left if self.replay.is_some() && self.time() > 0 => {
+                        let step = (self.interval().duration * (self.interval().tick_spacing as u32)).as_millis();
+                        self.seek(self.time().saturating_sub(step as u64));
+                        return true;
+                    }
+                    Key::Right if self.replay.is_some() => {
+                        let time = self.time();
+                        let step = (self.interval().duration * (self.interval().tick_spacing as u32)).as_millis();
+                        self.seek(time + (step as u64));
+                        return self.time() != time;
+                    }
+                    Key::Char('p') => {
+                        if let Some(ref mut replay) = self.replay {
+                            replay.paused = !replay.paused;
+                            return true;
+                        }
+                    }
                     Key::Char('+') => {
                         if self.interval_index < self.intervals.len() - 1 {
                             self.interval_index += 1;
@@ -172,20 +610,150 @@ impl Application {
 
             Screen::Streams => match event {
                 Event::Key(key) => match key {
-                    Key::Up => {}
-                    Key::Down => {}
-                    Key::Char(' ') => {}
-                    Key::Char('+') => {}
-                    Key::Char('-') => {}
+                    Key::Up if self.streams_selection_index > 0 => {
+                        self.streams_selection_index -= 1;
+                        self.scroll_to_stream_entry();
+                        return true;
+                    }
+                    Key::Down if self.streams_selection_index + 1 < self.filtered_stream_indices().len() => {
+                        self.streams_selection_index += 1;
+                        self.scroll_to_stream_entry();
+                        return true;
+                    }
+                    Key::Char(' ') => {
+                        if let Some(&index) = self.filtered_stream_indices().get(self.streams_selection_index) {
+                            // At least one stream must remain active at all times
+                            if !self.streams[index].active || self.active_streams().len() > 1 {
+                                let stream = &mut self.streams[index];
+                                stream.active = !stream.active;
+                                if stream.active {
+                                    // Inactive streams aren't updated, so any internal state is outdated
+                                    stream.stream.reset();
+                                }
+                                return true;
+                            }
+                        }
+                    }
+                    Key::Char('+') => {
+                        let indices = self.filtered_stream_indices();
+                        if self.streams_selection_index > 0 && self.streams_selection_index < indices.len() {
+                            self.streams.swap(
+                                indices[self.streams_selection_index - 1],
+                                indices[self.streams_selection_index],
+                            );
+                            self.streams_selection_index -= 1;
+                            self.scroll_to_stream_entry();
+                            return true;
+                        }
+                    }
+                    Key::Char('-') => {
+                        let indices = self.filtered_stream_indices();
+                        if self.streams_selection_index + 1 < indices.len() {
+                            self.streams.swap(
+                                indices[self.streams_selection_index],
+                                indices[self.streams_selection_index + 1],
+                            );
+                            self.streams_selection_index += 1;
+                            self.scroll_to_stream_entry();
+                            return true;
+                        }
+                    }
+                    Key::Char(c) if !c.is_control() => {
+                        self.streams_filter.push(*c);
+                        self.streams_selection_index = 0;
+                        self.streams_scroll_index = 0;
+                        return true;
+                    }
+                    Key::Backspace if !self.streams_filter.is_empty() => {
+                        self.streams_filter.pop();
+                        self.streams_selection_index = 0;
+                        self.streams_scroll_index = 0;
+                        return true;
+                    }
                     Key::Esc => {
+                        self.streams_filter.clear();
+                        self.streams_selection_index = 0;
+                        self.streams_scroll_index = 0;
                         self.screen = Screen::Main;
+
+                        // The set of active streams may have changed,
+                        // so the main screen's selection must be revalidated
+                        self.selection_index = min(self.selection_index, self.active_streams().len() - 1);
+                        self.scroll_index = 0;
+                        self.scroll_anchor = ScrollAnchor::Top;
+                        self.scroll_to_stream(self.selection_index);
                         return true;
                     }
                     _ => {}
                 },
                 Event::Mouse(MouseEvent::Press(mouse_button, _, _)) => match mouse_button {
-                    MouseButton::WheelUp => {}
-                    MouseButton::WheelDown => {}
+                    MouseButton::WheelUp => {
+                        return self.handle(&Event::Key(Key::Down));
+                    }
+                    MouseButton::WheelDown => {
+                        return self.handle(&Event::Key(Key::Up));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            },
+
+            Screen::Processes => match event {
+                Event::Key(key) => match key {
+                    // Values of live streams aren't sampled during replay
+                    Key::Char(' ') if self.replay.is_none() => {
+                        if let Some(pid) = self.processes_selection_pid {
+                            let streams = providers::process_streams(pid);
+
+                            if let Some(name) = streams.first().map(|s| s.name()) {
+                                self.add_streams(streams);
+                                self.screen = Screen::Main;
+
+                                // Select the first of the process's streams
+                                self.selection_index = self
+                                    .active_streams()
+                                    .iter()
+                                    .position(|s| s.stream.name() == name)
+                                    .unwrap();
+                                self.scroll_to_stream(self.selection_index);
+                                return true;
+                            }
+                        }
+                    }
+                    Key::Up if self.processes_selection_index > 0 => {
+                        self.select_process(self.processes_selection_index - 1);
+                        return true;
+                    }
+                    Key::Down if self.processes_selection_index + 1 < self.processes.processes.len() => {
+                        self.select_process(self.processes_selection_index + 1);
+                        return true;
+                    }
+                    Key::Char('c') if self.processes.sort_order != SortOrder::Cpu => {
+                        self.processes.set_sort_order(SortOrder::Cpu);
+                        self.find_selected_process();
+                        return true;
+                    }
+                    Key::Char('m') if self.processes.sort_order != SortOrder::Memory => {
+                        self.processes.set_sort_order(SortOrder::Memory);
+                        self.find_selected_process();
+                        return true;
+                    }
+                    Key::Esc => {
+                        self.processes_selection_index = 0;
+                        self.processes_selection_pid = None;
+                        self.processes_scroll_index = 0;
+                        self.screen = Screen::Main;
+                        return true;
+                    }
+                    _ => {}
+                },
+                Event::Mouse(MouseEvent::Press(mouse_button, _, _)) => match mouse_button {
+                    MouseButton::WheelUp => {
+                        return self.handle(&Event::Key(Key::Down));
+                    }
+                    MouseButton::WheelDown => {
+                        return self.handle(&Event::Key(Key::Up));
+                    }
                     _ => {}
                 },
                 _ => {}
@@ -198,6 +766,58 @@ impl Application {
     pub fn resize(&mut self, width: usize, height: usize) {
         self.width = width;
         self.height = height;
+        self.scroll_to_stream_entry();
+        self.scroll_to_process_entry();
+    }
+
+    fn scroll_to_stream_entry(&mut self) {
+        // The streams screen has a top bar and a bottom bar,
+        // and every stream entry occupies exactly one line
+        let visible_entries = max(self.height, 3) - 2;
+
+        if self.streams_selection_index < self.streams_scroll_index {
+            self.streams_scroll_index = self.streams_selection_index;
+        } else if self.streams_selection_index >= self.streams_scroll_index + visible_entries {
+            self.streams_scroll_index = self.streams_selection_index + 1 - visible_entries;
+        }
+    }
+
+    fn scroll_to_process_entry(&mut self) {
+        // Same layout as the streams screen
+        let visible_entries = max(self.height, 3) - 2;
+
+        if self.processes_selection_index < self.processes_scroll_index {
+            self.processes_scroll_index = self.processes_selection_index;
+        } else if self.processes_selection_index >= self.processes_scroll_index + visible_entries {
+            self.processes_scroll_index = self.processes_selection_index + 1 - visible_entries;
+        }
+    }
+
+    fn update_processes(&mut self) {
+        self.processes.update();
+        self.find_selected_process();
+    }
+
+    fn select_process(&mut self, index: usize) {
+        self.processes_selection_index = index;
+        self.processes_selection_pid = self.processes.processes.get(index).map(|p| p.pid);
+        self.scroll_to_process_entry();
+    }
+
+    // Moves the selection to the row of the selected process, whose position
+    // changes when the table is updated or sorted. If the process has exited,
+    // the process that is now at the same position is selected instead.
+    fn find_selected_process(&mut self) {
+        let position = self
+            .processes_selection_pid
+            .and_then(|pid| self.processes.processes.iter().position(|p| p.pid == pid));
+
+        self.select_process(position.unwrap_or_else(|| {
+            min(
+                self.processes_selection_index,
+                self.processes.processes.len().saturating_sub(1),
+            )
+        }));
     }
 
     fn scroll_to_stream(&mut self, index: usize) {
@@ -240,60 +860,256 @@ impl Application {
         }
     }
 
+    /// Updates the values of all active streams, waiting for them to arrive.
     pub fn update_streams(&mut self) {
+        self.start_update();
+        self.sampler.wait();
+        self.finish_update();
+    }
+
+    /// Starts updating the values of all active streams, without waiting for them.
+    /// The update is completed by passing the samples received from `samples`
+    /// to `receive_sample`, or by calling `finish_update` at the deadline
+    /// returned by `update_deadline`.
+    pub fn start_update(&mut self) {
+        // Any update still in progress would otherwise be lost
+        self.finish_update();
+
+        // The process table always shows live values, even during replay
+        if self.screen == Screen::Processes {
+            self.update_processes();
+        }
+
+        if let Some(ref replay) = self.replay {
+            let time = if replay.paused {
+                // The history might have to be rebuilt for a new interval
+                self.time()
+            } else {
+                self.time() + (self.interval().duration.as_millis() as u64)
+            };
+            self.seek(time);
+            return;
+        }
+
+        // Waiting for unresponsive streams must never delay the next update
+        let timeout = min(self.timeout, self.interval().duration);
+
+        // Consecutive values are always one interval apart, so that each falls into
+        // a bucket of its own, but delays in updating cause corresponding gaps
+        let interval = self.interval().duration.as_millis() as u64;
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        let aligned = ((elapsed + (interval / 2)) / interval) * interval;
+        let time = match self.time {
+            // After switching intervals, the previous time is one interval of the
+            // previous length behind, so the new interval would leave it ahead
+            Some(time) if self.time_interval != interval => max(time, aligned),
+            Some(time) => max(time + interval, aligned),
+            None => aligned,
+        };
+        self.time_interval = interval;
+
+        // Metrics are served for all streams, not just those shown
+        let sample_all = self.metrics_server.is_some();
+
         for stream in &mut self.streams {
-            if stream.active {
-                let value = stream.stream.value();
+            stream.sampling = stream.active || sample_all;
+        }
 
-                if let Some(number) = value {
-                    assert!(number.is_finite());
-                    if let Some(min) = stream.stream.min() {
-                        assert!(number >= min);
-                    }
-                    if let Some(max) = stream.stream.max() {
-                        assert!(number <= max);
-                    }
-                }
+        let sampled_streams = self
+            .streams
+            .iter()
+            .filter(|s| s.sampling)
+            .map(|s| &s.stream)
+            .collect::<Vec<_>>();
 
-                stream.values.push_back(value);
+        // Streams may be reordered or (de)activated before the update completes,
+        // so they are identified by name
+        let names = sampled_streams.iter().map(|s| s.name()).collect();
 
-                if stream.values.len() > VALUE_HISTORY_SIZE {
-                    stream.values.pop_front();
-                }
+        self.sampler.start(&sampled_streams, timeout);
+        self.pending_update = Some((time, names));
+
+        if self.sampler.complete() {
+            self.finish_update();
+        }
+    }
+
+    /// Returns the channel through which samples arrive while updating.
+    pub fn samples(&self) -> Receiver<Sample> {
+        self.sampler.samples()
+    }
+
+    /// Adds the given sample to the update in progress.
+    /// Returns `true` if this completed the update.
+    pub fn receive_sample(&mut self, sample: Sample) -> bool {
+        if self.pending_update.is_some() && self.sampler.receive(sample) {
+            self.finish_update();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the time at which the update in progress should be completed
+    /// by calling `finish_update`, or `None` if no update is in progress.
+    pub fn update_deadline(&self) -> Option<Instant> {
+        self.pending_update.as_ref().and(self.sampler.deadline())
+    }
+
+    /// Completes the update in progress, if any. Streams that haven't
+    /// provided their values yet are treated as having no value.
+    pub fn finish_update(&mut self) {
+        let (time, names) = match self.pending_update.take() {
+            Some(pending_update) => pending_update,
+            None => return,
+        };
+
+        let values = names.into_iter().zip(self.sampler.finish()).collect::<HashMap<_, _>>();
+
+        self.time = Some(time);
+
+        for stream in &mut self.streams {
+            stream.sampling = false;
+            if let Some(&value) = values.get(&stream.stream.name()) {
+                stream.push_value(time, value, None);
             }
         }
+
+        if let Some(ref mut recorder) = self.recorder {
+            let values = self
+                .streams
+                .iter()
+                .filter(|s| s.active)
+                .map(|s| (&s.stream as &dyn Stream, s.last_value))
+                .collect::<Vec<_>>();
+
+            if let Err(error) = recorder.record(self.start_timestamp + time, &values) {
+                self.recorder = None;
+                self.recording_error = Some(error);
+            }
+        }
+
+        if let Some(ref metrics_server) = self.metrics_server {
+            let values = self
+                .streams
+                .iter()
+                .map(|s| (&s.stream as &dyn Stream, s.last_value))
+                .collect::<Vec<_>>();
+
+            metrics_server.update(&values);
+        }
     }
 
     pub fn reset_streams(&mut self) {
         for stream in &mut self.streams {
-            // TODO: Reset stream's internal state
-            stream.values.clear();
+            stream.stream.reset();
+        }
+    }
+}
+
+// Returns the resolutions at which the history of each stream is stored,
+// which correspond to the available intervals
+fn resolutions(intervals: &[Interval]) -> Vec<u64> {
+    intervals.iter().map(|i| i.duration.as_millis() as u64).collect()
+}
+
+// Converts a glob pattern, in which `*` matches any sequence of characters
+// and `?` matches any single character, to a case-insensitive regular expression
+fn glob_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("(?i)^");
+
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
         }
     }
+
+    regex.push('$');
+
+    Regex::new(&regex).unwrap()
+}
+
+pub struct Replay {
+    recording: Recording,
+    // Index of the first sample that hasn't been added to the history yet
+    position: usize,
+    // If set, the history has only been built at this resolution
+    resolution: Option<u64>,
+    pub paused: bool,
 }
 
 #[derive(PartialEq, Eq, Hash)]
 pub enum Screen {
     Main,
     Streams,
+    Processes,
 }
 
 pub struct StreamWrapper {
-    pub stream: Box<dyn Stream>,
-    pub values: VecDeque<Option<f64>>,
+    pub stream: SharedStream,
+    pub history: History,
+    /// The most recent value, or `None` if the stream
+    /// didn't return a valid value during the last update
+    pub last_value: Option<f64>,
     pub active: bool,
     pub expanded: bool,
+    /// Number of invalid values (not finite or out of range) the stream has returned
+    pub errors: usize,
+    // Whether the stream's value is being computed by the update in progress
+    sampling: bool,
 }
 
 impl StreamWrapper {
-    fn new(stream: Box<dyn Stream>) -> Self {
+    /// Returns `true` if the stream failed to provide its latest value in time
+    /// and is still busy computing it.
+    pub fn stalled(&self) -> bool {
+        !self.sampling && self.stream.busy()
+    }
+
+    fn new(stream: Box<dyn Stream>, resolutions: &[u64]) -> Self {
         StreamWrapper {
-            stream,
-            values: VecDeque::new(),
-            active: true,
+            active: stream.active_by_default(),
+            stream: SharedStream::new(stream),
+            history: History::new(resolutions, HISTORY_SIZE),
+            last_value: None,
             expanded: false,
+            errors: 0,
+            sampling: false,
         }
     }
+
+    // Records the value at the given resolution only, or at all resolutions if `None`
+    fn push_value(&mut self, time: u64, value: Option<f64>, resolution: Option<u64>) {
+        // Invalid values are a bug in the stream, or a glitch in the underlying sensor.
+        // Either way, they shouldn't bring down the whole application.
+        let value = value.and_then(|number| {
+            if !number.is_finite() {
+                self.errors += 1;
+                return None;
+            }
+
+            let min = self.stream.min().unwrap_or(number);
+            let max = self.stream.max().unwrap_or(number);
+
+            if number < min || number > max {
+                self.errors += 1;
+                Some(number.max(min).min(max))
+            } else {
+                Some(number)
+            }
+        });
+
+        if let Some(number) = value {
+            match resolution {
+                Some(resolution) => self.history.push_at(resolution, time, number),
+                None => self.history.push(time, number),
+            }
+        }
+
+        self.last_value = value;
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -315,6 +1131,23 @@ impl Interval {
             tick_spacing,
         }
     }
+
+    /// Returns all intervals the user can choose from, from shortest to longest.
+    pub fn all() -> Vec<Self> {
+        vec![
+            Interval::new(100, 10),
+            Interval::new(200, 10),
+            Interval::new(500, 10),
+            Interval::new(1_000, 10),
+            Interval::new(2_000, 15),
+            Interval::new(3_000, 10),
+            Interval::new(5_000, 12),
+            Interval::new(10_000, 12),
+            Interval::new(30_000, 10),
+            Interval::new(60_000, 10),
+            Interval::new(300_000, 12),
+        ]
+    }
 }
 
 #[derive(Clone)]
@@ -331,3 +1164,322 @@ impl MenuItem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordedStream;
+
+    fn application(names: &[&str]) -> Application {
+        let streams = names
+            .iter()
+            .map(|name| Stream::new(*name, "", || Some(0.0), None, None, "", None, 0, false))
+            .collect();
+        Application::new(80, 24, streams)
+    }
+
+    fn names(application: &Application) -> Vec<String> {
+        application.streams.iter().map(|s| s.stream.name()).collect()
+    }
+
+    fn press(application: &mut Application, key: Key) -> bool {
+        application.handle(&Event::Key(key))
+    }
+
+    #[test]
+    fn test_toggle_streams() {
+        let mut application = application(&["A", "B"]);
+        application.screen = Screen::Streams;
+
+        assert!(press(&mut application, Key::Char(' ')));
+        assert!(!application.streams[0].active);
+
+        // The last active stream cannot be deactivated
+        assert!(press(&mut application, Key::Down));
+        assert!(!press(&mut application, Key::Char(' ')));
+        assert!(application.streams[1].active);
+
+        assert!(press(&mut application, Key::Esc));
+        assert!(application.screen == Screen::Main);
+        assert_eq!(application.active_streams().len(), 1);
+    }
+
+    #[test]
+    fn test_reorder_streams() {
+        let mut application = application(&["A", "B", "C"]);
+        application.screen = Screen::Streams;
+
+        assert!(!press(&mut application, Key::Char('+')));
+        assert!(press(&mut application, Key::Char('-')));
+        assert_eq!(names(&application), vec!["B", "A", "C"]);
+        assert!(press(&mut application, Key::Char('-')));
+        assert_eq!(names(&application), vec!["B", "C", "A"]);
+        assert!(!press(&mut application, Key::Char('-')));
+        assert!(press(&mut application, Key::Char('+')));
+        assert_eq!(names(&application), vec!["B", "A", "C"]);
+        assert_eq!(application.streams_selection_index, 1);
+    }
+
+    #[test]
+    fn test_filter_streams() {
+        let mut application = application(&["CPU", "Core1", "Core2", "Mem"]);
+        application.screen = Screen::Streams;
+
+        assert!(press(&mut application, Key::Char('c')));
+        assert!(press(&mut application, Key::Char('o')));
+        assert_eq!(application.filtered_stream_indices(), vec![1, 2]);
+
+        // Reordering only swaps streams that match the filter
+        assert!(press(&mut application, Key::Char('-')));
+        assert_eq!(names(&application), vec!["CPU", "Core2", "Core1", "Mem"]);
+
+        assert!(press(&mut application, Key::Backspace));
+        assert_eq!(application.filtered_stream_indices(), vec![0, 1, 2]);
+        assert!(press(&mut application, Key::Backspace));
+        assert!(!press(&mut application, Key::Backspace));
+        assert_eq!(application.filtered_stream_indices(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_select_streams() {
+        let mut application = application(&["CPU", "Core1", "Mem", "ethRx", "ethTx", "wlanRx"]);
+
+        assert!(!application.select_streams(&["Disk*"]));
+        assert_eq!(application.active_streams().len(), 6);
+
+        assert!(application.select_streams(&["mem", "*Rx", "Core?"]));
+        assert_eq!(
+            names(&application),
+            vec!["Mem", "ethRx", "wlanRx", "Core1", "CPU", "ethTx"]
+        );
+        assert_eq!(
+            application
+                .active_streams()
+                .iter()
+                .map(|s| s.stream.name())
+                .collect::<Vec<_>>(),
+            vec!["Mem", "ethRx", "wlanRx", "Core1"],
+        );
+    }
+
+    #[test]
+    fn test_processes_screen() {
+        let mut application = application(&["A"]);
+
+        assert!(press(&mut application, Key::Char('t')));
+        assert!(application.screen == Screen::Processes);
+        assert!(!application.processes.processes.is_empty());
+
+        assert!(!press(&mut application, Key::Char('c')));
+        assert!(press(&mut application, Key::Down));
+        let pid = application.processes.processes[1].pid;
+        assert!(press(&mut application, Key::Char('m')));
+        // The selection follows the process to its new row
+        assert_eq!(
+            application.processes.processes[application.processes_selection_index].pid,
+            pid,
+        );
+        application.update_streams();
+        let rss = application
+            .processes
+            .processes
+            .iter()
+            .map(|p| p.rss)
+            .collect::<Vec<_>>();
+        assert!(rss.windows(2).all(|w| w[0] >= w[1]));
+
+        assert!(press(&mut application, Key::Esc));
+        assert!(application.screen == Screen::Main);
+    }
+
+    #[test]
+    fn test_add_streams() {
+        let mut application = application(&["A", "B"]);
+        application.streams[1].active = false;
+
+        application.add_streams(vec![
+            Stream::new("B", "", || Some(0.0), None, None, "", None, 0, false),
+            Stream::new("C", "", || Some(0.0), None, None, "", None, 0, false).inactive_by_default(),
+        ]);
+
+        assert_eq!(names(&application), vec!["A", "B", "C"]);
+        assert_eq!(application.active_streams().len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut values = vec![
+            Some(50.0),
+            Some(150.0),
+            Some(-1.0),
+            Some(f64::NAN),
+            Some(f64::INFINITY),
+            None,
+        ];
+        values.reverse();
+
+        let mut application = Application::new(
+            80,
+            24,
+            vec![Stream::new(
+                "A",
+                "",
+                move || values.pop().unwrap(),
+                Some(0.0),
+                Some(100.0),
+                "",
+                None,
+                0,
+                false,
+            )],
+        );
+
+        for _ in 0..6 {
+            application.update_streams();
+        }
+
+        let stream = &application.streams[0];
+        assert_eq!(
+            stream
+                .history
+                .buckets(1_000, application.time(), 6)
+                .iter()
+                .map(|b| b.map(|b| b.avg()))
+                .collect::<Vec<_>>(),
+            vec![Some(50.0), Some(100.0), Some(0.0), None, None, None],
+        );
+        assert_eq!(stream.errors, 4);
+    }
+
+    #[test]
+    fn test_switch_interval() {
+        let mut application = application(&["A"]);
+
+        application.set_interval(Duration::from_millis(100));
+        application.start = Instant::now() - Duration::from_millis(1_500);
+        application.update_streams();
+        assert_eq!(application.time(), 1_500);
+
+        // The time must not move a full minute ahead of the elapsed time
+        application.set_interval(Duration::from_secs(60));
+        application.start = Instant::now() - Duration::from_millis(1_600);
+        application.update_streams();
+        assert_eq!(application.time(), 1_500);
+
+        application.start = Instant::now() - Duration::from_millis(61_600);
+        application.update_streams();
+        assert_eq!(application.time(), 61_500);
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut application = application(&["A", "B", "C", "D"]);
+
+        application.apply_config(&Config {
+            interval: Some(5_000),
+            timeout: None,
+            exclude_virtual_interfaces: Some(true),
+            streams: vec![
+                StreamConfig {
+                    name: String::from("C"),
+                    active: true,
+                    expanded: true,
+                },
+                StreamConfig {
+                    name: String::from("X"),
+                    active: true,
+                    expanded: false,
+                },
+                StreamConfig {
+                    name: String::from("A"),
+                    active: false,
+                    expanded: false,
+                },
+            ],
+        });
+
+        assert_eq!(application.interval().duration, Duration::from_secs(5));
+        // Unconfigured streams follow the configured streams that precede them by default
+        assert_eq!(names(&application), vec!["C", "D", "A", "B"]);
+        assert!(application.streams[0].expanded);
+        assert!(!application.streams[2].active);
+
+        let config = application.config();
+        assert_eq!(config.interval, Some(5_000));
+        assert_eq!(config.exclude_virtual_interfaces, Some(true));
+        // Streams that don't exist are kept after the stream they followed
+        assert_eq!(
+            config.streams.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["C", "X", "D", "A", "B"],
+        );
+    }
+
+    #[test]
+    fn test_active_by_default() {
+        let streams = vec![
+            Stream::new("A", "", || None, None, None, "", None, 0, false).inactive_by_default(),
+            Stream::new("B", "", || None, None, None, "", None, 0, false),
+        ];
+        let application = Application::new(80, 24, streams);
+        assert!(!application.streams[0].active);
+        assert!(application.streams[1].active);
+
+        // At least one stream is always active
+        let streams = vec![Stream::new("A", "", || None, None, None, "", None, 0, false).inactive_by_default()];
+        let application = Application::new(80, 24, streams);
+        assert!(application.streams[0].active);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut application = application(&["A"]);
+
+        application.replay(Recording {
+            streams: vec![RecordedStream {
+                name: String::from("A"),
+                description: String::new(),
+                min: None,
+                max: None,
+            }],
+            samples: (0..7).map(|i| (50_000 + i * 5_000, 0, i as f64)).collect(),
+        });
+
+        // Replay starts paused at the end of the recording
+        assert_eq!(application.time(), 30_000);
+        assert!(application.replay.as_ref().unwrap().paused);
+
+        let values = |application: &Application| {
+            application.streams[0]
+                .history
+                .buckets(1_000, application.time(), 11)
+                .iter()
+                .map(|b| b.map(|b| b.avg()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(press(&mut application, Key::Left));
+        assert_eq!(application.time(), 20_000);
+        assert_eq!(values(&application)[10], Some(4.0));
+        assert_eq!(values(&application)[5], Some(3.0));
+        assert_eq!(values(&application)[0], Some(2.0));
+
+        // After moving backward, the history is only rebuilt at the current interval,
+        // and is rebuilt again when switching intervals
+        assert!(press(&mut application, Key::Char('-')));
+        application.update_streams();
+        assert_eq!(application.time(), 20_000);
+        let bucket = application.streams[0].history.buckets(500, 20_000, 1)[0];
+        assert_eq!(bucket.map(|b| b.avg()), Some(4.0));
+        assert!(press(&mut application, Key::Char('+')));
+
+        assert!(press(&mut application, Key::Char('p')));
+        application.update_streams();
+        assert_eq!(application.time(), 21_000);
+
+        assert!(press(&mut application, Key::Right));
+        assert_eq!(application.time(), 30_000);
+        assert_eq!(values(&application)[10], Some(6.0));
+        assert!(application.replay.as_ref().unwrap().paused);
+        assert!(!press(&mut application, Key::Right));
+    }
+}
@@ -0,0 +1,269 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::mem::size_of;
+use std::time::Instant;
+
+const PROC_PATH: &str = "/proc";
+const AUXV_PATH: &str = "/proc/self/auxv";
+
+// Keys of entries in the auxiliary vector (see getauxval(3))
+const AT_PAGESZ: usize = 6;
+const AT_CLKTCK: usize = 17;
+
+// Values that virtually all Linux systems use
+const DEFAULT_PAGE_SIZE: u64 = 4096;
+const DEFAULT_CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// Information about a running process.
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    /// Percentage of one CPU core's time the process used since the last update,
+    /// or `None` if the process wasn't running at that time
+    pub cpu: Option<f64>,
+    /// Resident set size, in bytes
+    pub rss: u64,
+    pub threads: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    Cpu,
+    Memory,
+}
+
+/// All running processes, as of the last update.
+pub struct ProcessTable {
+    pub processes: Vec<Process>,
+    pub sort_order: SortOrder,
+    // CPU time used by each process as of the last update, in clock ticks,
+    // keyed by PID and start time, so that a new process reusing the PID
+    // of one that has exited isn't charged with the difference
+    last_cpu_times: HashMap<(u32, u64), u64>,
+    last_time: Option<Instant>,
+}
+
+impl ProcessTable {
+    pub fn new() -> Self {
+        ProcessTable {
+            processes: Vec::new(),
+            sort_order: SortOrder::Cpu,
+            last_cpu_times: HashMap::new(),
+            last_time: None,
+        }
+    }
+
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let elapsed = self.last_time.map(|time| ((now - time).as_millis() as f64) / 1000.0);
+
+        let page_size = page_size();
+        let clock_ticks_per_second = clock_ticks_per_second();
+
+        let mut cpu_times = HashMap::new();
+        let mut processes = Vec::new();
+
+        for pid in pids() {
+            // Processes can exit at any time
+            let (stat, rss) = match (stat(pid), rss(pid, page_size)) {
+                (Some(stat), Some(rss)) => (stat, rss),
+                _ => continue,
+            };
+
+            let cpu = match (self.last_cpu_times.get(&(pid, stat.start_time)), elapsed) {
+                (Some(&last_cpu_time), Some(elapsed)) if elapsed > 0.0 => Some(
+                    (stat.cpu_time.saturating_sub(last_cpu_time) as f64) / clock_ticks_per_second / elapsed * 100.0,
+                ),
+                _ => None,
+            };
+
+            cpu_times.insert((pid, stat.start_time), stat.cpu_time);
+
+            processes.push(Process {
+                pid,
+                name: stat.name,
+                cpu,
+                rss,
+                threads: stat.threads,
+            });
+        }
+
+        self.processes = processes;
+        self.last_cpu_times = cpu_times;
+        self.last_time = Some(now);
+
+        self.sort();
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        let cpu = |p: &Process| p.cpu.unwrap_or(0.0);
+
+        match self.sort_order {
+            SortOrder::Cpu => self.processes.sort_by(|a, b| {
+                cpu(b)
+                    .partial_cmp(&cpu(a))
+                    .unwrap_or(Ordering::Equal)
+                    .then(b.rss.cmp(&a.rss))
+                    .then(a.pid.cmp(&b.pid))
+            }),
+            SortOrder::Memory => self.processes.sort_by(|a, b| b.rss.cmp(&a.rss).then(a.pid.cmp(&b.pid))),
+        }
+    }
+}
+
+/// Information from `/proc/<pid>/stat`.
+pub struct Stat {
+    pub name: String,
+    /// CPU time used in user and kernel mode, in clock ticks
+    pub cpu_time: u64,
+    pub threads: u64,
+    /// Time the process started after system boot, in clock ticks.
+    /// Together with the PID, this identifies a process uniquely,
+    /// because PIDs are reused after processes exit.
+    pub start_time: u64,
+}
+
+/// Returns the PIDs of all running processes.
+pub fn pids() -> Vec<u32> {
+    match fs::read_dir(PROC_PATH) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter_map(|name| name.parse::<u32>().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Returns the PIDs of all running processes with the given name.
+pub fn pids_named(name: &str) -> Vec<u32> {
+    let mut pids = pids()
+        .into_iter()
+        .filter(|&pid| match stat(pid) {
+            Some(stat) => stat.name == name,
+            None => false,
+        })
+        .collect::<Vec<_>>();
+
+    pids.sort();
+    pids
+}
+
+pub fn stat(pid: u32) -> Option<Stat> {
+    parse_stat(&fs::read_to_string(format!("{}/{}/stat", PROC_PATH, pid)).ok()?)
+}
+
+/// Returns the resident set size of the given process, in bytes.
+pub fn rss(pid: u32, page_size: u64) -> Option<u64> {
+    parse_statm(&fs::read_to_string(format!("{}/{}/statm", PROC_PATH, pid)).ok()?).map(|pages| pages * page_size)
+}
+
+/// Returns the size of a memory page, in bytes.
+pub fn page_size() -> u64 {
+    auxiliary_value(AT_PAGESZ).map_or(DEFAULT_PAGE_SIZE, |size| size as u64)
+}
+
+/// Returns the number of clock ticks per second, which is the unit of CPU time in `/proc`.
+pub fn clock_ticks_per_second() -> f64 {
+    auxiliary_value(AT_CLKTCK).map_or(DEFAULT_CLOCK_TICKS_PER_SECOND, |ticks| ticks as f64)
+}
+
+// Reads a value from the auxiliary vector the kernel passed to this process,
+// which is the only way to get system constants like the page size without `unsafe`
+fn auxiliary_value(key: usize) -> Option<usize> {
+    parse_auxiliary_vector(&fs::read(AUXV_PATH).ok()?, key)
+}
+
+// The auxiliary vector is a sequence of native-endian key/value pairs of machine words
+fn parse_auxiliary_vector(auxv: &[u8], key: usize) -> Option<usize> {
+    let words = auxv
+        .chunks_exact(size_of::<usize>())
+        .map(|bytes| usize::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect::<Vec<_>>();
+
+    words.chunks_exact(2).find(|pair| pair[0] == key).map(|pair| pair[1])
+}
+
+fn parse_stat(stat: &str) -> Option<Stat> {
+    // The name is enclosed in parentheses and can itself contain
+    // spaces and parentheses, but the fields following it cannot
+    let name_start = stat.find('(')? + 1;
+    let name_end = stat.rfind(')')?;
+
+    if name_end < name_start {
+        return None;
+    }
+
+    // Field numbers as documented in proc(5), minus the 3 fields up to and including the name
+    let fields = stat[name_end + 1..].split_whitespace().collect::<Vec<_>>();
+    let field = |number: usize| fields.get(number - 3).and_then(|f| f.parse::<u64>().ok());
+
+    Some(Stat {
+        name: String::from(&stat[name_start..name_end]),
+        cpu_time: field(14)? + field(15)?,
+        threads: field(20)?,
+        start_time: field(22)?,
+    })
+}
+
+// Returns the resident set size in pages
+fn parse_statm(statm: &str) -> Option<u64> {
+    statm.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = parse_stat(
+            "1234 (Web Content (1)) S 1 1234 1234 0 -1 4194560 \
+             50000 0 10 0 1500 250 0 0 20 0 27 0 12345 3000000000 60000 \
+             18446744073709551615 1 1 0 0 0 0 0 4096 1260 0 0 0 17 3 0 0 0 0 0",
+        )
+        .unwrap();
+        assert_eq!(stat.name, "Web Content (1)");
+        assert_eq!(stat.cpu_time, 1750);
+        assert_eq!(stat.threads, 27);
+        assert_eq!(stat.start_time, 12345);
+
+        assert!(parse_stat("1234 (bash) S 1").is_none());
+        assert_eq!(parse_statm("754321 60000 12000 100 0 500000 0\n"), Some(60000));
+    }
+
+    #[test]
+    fn test_parse_auxiliary_vector() {
+        let auxv = [33, 140_000, AT_PAGESZ, 16384, AT_CLKTCK, 100, 0, 0]
+            .iter()
+            .flat_map(|word: &usize| word.to_ne_bytes().to_vec())
+            .collect::<Vec<_>>();
+
+        assert_eq!(parse_auxiliary_vector(&auxv, AT_PAGESZ), Some(16384));
+        assert_eq!(parse_auxiliary_vector(&auxv, AT_CLKTCK), Some(100));
+        assert_eq!(parse_auxiliary_vector(&auxv, 99), None);
+    }
+}
@@ -0,0 +1,293 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::stream::Stream;
+
+// Time after which unresponsive clients are disconnected
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the latest stream values over HTTP at `/metrics`,
+/// in the Prometheus text exposition format.
+pub struct MetricsServer {
+    metrics: Arc<Mutex<String>>,
+    // Metric names of the streams added so far, by stream name
+    names: HashMap<String, String>,
+    taken_names: HashSet<String>,
+}
+
+impl MetricsServer {
+    /// Starts listening on the given address. Requests are handled on background threads.
+    pub fn start(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+
+        let metrics = Arc::new(Mutex::new(String::new()));
+        let server_metrics = metrics.clone();
+
+        thread::spawn(move || {
+            for connection in listener.incoming().flatten() {
+                let metrics = server_metrics.clone();
+                thread::spawn(move || {
+                    // There is nobody to report errors to,
+                    // and the client can simply try again
+                    handle(connection, &metrics).ok();
+                });
+            }
+        });
+
+        Ok(MetricsServer {
+            metrics,
+            names: HashMap::new(),
+            taken_names: HashSet::new(),
+        })
+    }
+
+    /// Assigns metric names to the given streams, unless they already have one.
+    /// Where several streams map to the same metric name, the name is made unique
+    /// in the order in which the streams are added, rather than the order
+    /// in which they are displayed, so that each stream is always exported
+    /// under the same name, even if the user reorders the streams.
+    pub fn add_streams(&mut self, streams: &[&dyn Stream]) {
+        for stream in streams {
+            if !self.names.contains_key(&stream.name()) {
+                let name = unique_metric_name(&stream.name(), &stream.unit(), stream.rate(), &mut self.taken_names);
+                self.names.insert(stream.name(), name);
+            }
+        }
+    }
+
+    /// Replaces the served metrics with the given stream values.
+    /// Streams that haven't been added with `add_streams` are omitted.
+    pub fn update(&self, values: &[(&dyn Stream, Option<f64>)]) {
+        *self.metrics.lock().unwrap() = metrics(values, &self.names);
+    }
+}
+
+fn handle(mut connection: TcpStream, metrics: &Mutex<String>) -> io::Result<()> {
+    connection.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    connection.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(connection.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Headers are ignored, but must be read before responding
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == "/metrics" || path.starts_with("/metrics?") => {
+            ("200 OK", metrics.lock().unwrap().clone())
+        }
+        (Some("GET"), _) => ("404 Not Found", String::from("Metrics are available at /metrics\n")),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        connection,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    )?;
+
+    connection.flush()
+}
+
+/// Returns the given stream values in the Prometheus text exposition format,
+/// using the given metric names (by stream name).
+/// Streams without a value or a metric name are omitted.
+pub fn metrics(values: &[(&dyn Stream, Option<f64>)], names: &HashMap<String, String>) -> String {
+    let mut string = String::new();
+
+    for (stream, value) in values {
+        if let (Some(name), Some(value)) = (names.get(&stream.name()), value) {
+            string.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+                name,
+                stream.description().replace('\\', "\\\\").replace('\n', "\\n"),
+                name,
+                name,
+                value,
+            ));
+        }
+    }
+
+    string
+}
+
+// Returns the metric name for the stream, with a number inserted before the unit suffix
+// if that name is already taken (different stream names can map to the same metric name,
+// e.g. because punctuation is removed)
+fn unique_metric_name(stream_name: &str, unit: &str, rate: bool, names: &mut HashSet<String>) -> String {
+    let (name, suffix) = metric_name(stream_name, unit, rate);
+
+    let join = |name: &str| {
+        if suffix.is_empty() {
+            String::from(name)
+        } else {
+            format!("{}_{}", name, suffix)
+        }
+    };
+
+    let mut unique_name = join(&name);
+    let mut number = 1;
+
+    while !names.insert(unique_name.clone()) {
+        number += 1;
+        unique_name = join(&format!("{}_{}", name, number));
+    }
+
+    unique_name
+}
+
+// Converts a stream name such as `eth0Rx` into a metric name such as `hegemon_eth0_rx`
+// and a unit suffix such as `bytes_per_second`, following Prometheus naming conventions
+// (snake case, base unit as suffix). The suffix is empty if the name already ends with it.
+fn metric_name(stream_name: &str, unit: &str, rate: bool) -> (String, String) {
+    let chars = stream_name.chars().collect::<Vec<_>>();
+
+    let mut name = String::from("hegemon_");
+
+    for (i, &c) in chars.iter().enumerate() {
+        let next_lowercase = match chars.get(i + 1) {
+            Some(next) => next.is_lowercase(),
+            None => false,
+        };
+
+        let word_start = c.is_uppercase() && i > 0 && (!chars[i - 1].is_uppercase() || next_lowercase);
+
+        if (word_start || !c.is_ascii_alphanumeric()) && !name.ends_with('_') {
+            name.push('_');
+        }
+
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        }
+    }
+
+    let unit_suffix = match unit {
+        "" => "",
+        "%" => "percent",
+        "B" => "bytes",
+        "°C" => "celsius",
+        "Hz" => "hertz",
+        "V" => "volts",
+        "W" => "watts",
+        "A" => "amperes",
+        "J" => "joules",
+        "s" => "seconds",
+        "RPM" => "rpm",
+        _ => "",
+    };
+
+    let suffix = match (unit_suffix, rate) {
+        ("", true) => String::from("per_second"),
+        (unit_suffix, true) => format!("{}_per_second", unit_suffix),
+        (unit_suffix, false) => String::from(unit_suffix),
+    };
+
+    let name = String::from(name.trim_end_matches('_'));
+
+    if name.ends_with(&suffix) {
+        (name, String::new())
+    } else {
+        (name, suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(stream_name: &str, unit: &str, rate: bool) -> String {
+        unique_metric_name(stream_name, unit, rate, &mut HashSet::new())
+    }
+
+    #[test]
+    fn test_metric_name() {
+        assert_eq!(name("CPU", "%", false), "hegemon_cpu_percent");
+        assert_eq!(name("Core12", "%", false), "hegemon_core12_percent");
+        assert_eq!(name("eth0Rx", "B", true), "hegemon_eth0_rx_bytes_per_second");
+        assert_eq!(name("SwapIn", "", true), "hegemon_swap_in_per_second");
+        assert_eq!(name("Mem", "B", false), "hegemon_mem_bytes");
+        assert_eq!(name("CPUTemp", "°C", false), "hegemon_cpu_temp_celsius");
+        assert_eq!(name("Package 1Temp", "°C", false), "hegemon_package_1_temp_celsius");
+        assert_eq!(name("Fan-1", "RPM", false), "hegemon_fan_1_rpm");
+        assert_eq!(name("Load", "", false), "hegemon_load");
+    }
+
+    #[test]
+    fn test_unique_metric_name() {
+        let mut names = HashSet::new();
+        assert_eq!(
+            unique_metric_name("FS /a-b", "B", false, &mut names),
+            "hegemon_fs_a_b_bytes"
+        );
+        assert_eq!(
+            unique_metric_name("FS /a_b", "B", false, &mut names),
+            "hegemon_fs_a_b_2_bytes"
+        );
+        assert_eq!(
+            unique_metric_name("FS /a b", "B", false, &mut names),
+            "hegemon_fs_a_b_3_bytes"
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let cpu = Stream::new("CPU", "CPU usage", || None, None, None, "%", None, 1, false);
+        let mem = Stream::new("Mem", "Memory", || None, None, None, "B", None, 1, false);
+
+        let names = [("CPU", "hegemon_cpu_percent"), ("Mem", "hegemon_mem_bytes")]
+            .iter()
+            .map(|&(stream_name, name)| (String::from(stream_name), String::from(name)))
+            .collect();
+
+        assert_eq!(
+            metrics(&[(&*cpu, Some(12.5)), (&*mem, None)], &names),
+            "# HELP hegemon_cpu_percent CPU usage\n# TYPE hegemon_cpu_percent gauge\nhegemon_cpu_percent 12.5\n",
+        );
+    }
+
+    #[test]
+    fn test_metrics_server_names() {
+        let a = Stream::new("FS /a-b", "A", || None, None, None, "B", None, 1, false);
+        let b = Stream::new("FS /a_b", "B", || None, None, None, "B", None, 1, false);
+
+        let mut metrics_server = MetricsServer::start("127.0.0.1:0").unwrap();
+        metrics_server.add_streams(&[&*a, &*b]);
+
+        // Reordering the streams doesn't change their names
+        metrics_server.update(&[(&*b, Some(2.0)), (&*a, Some(1.0))]);
+        let metrics = metrics_server.metrics.lock().unwrap().clone();
+        assert!(metrics.contains("hegemon_fs_a_b_2_bytes 2\n"));
+        assert!(metrics.contains("hegemon_fs_a_b_bytes 1\n"));
+    }
+}
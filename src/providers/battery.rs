@@ -0,0 +1,267 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::providers::read_string;
+use crate::stream::{Stream, StreamProvider};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+const DISCHARGING: &str = "Discharging";
+const CHARGING: &str = "Charging";
+
+// Depending on the driver, batteries report either energy (in microwatt-hours)
+// or charge (in microampere-hours), and the corresponding rate of change
+// (in microwatts or microamperes). These are the files for each case, for
+// the current value, the value when full, the value when new, and the rate.
+const ENERGY_FILES: &[[&str; 4]] = &[
+    ["energy_now", "energy_full", "energy_full_design", "power_now"],
+    ["charge_now", "charge_full", "charge_full_design", "current_now"],
+];
+
+pub struct BatteryStreamProvider {}
+
+impl StreamProvider for BatteryStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        power_supply_streams(Path::new(POWER_SUPPLY_PATH))
+    }
+}
+
+fn power_supply_streams(power_supply_path: &Path) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
+
+    let mut supplies = match fs::read_dir(power_supply_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect::<Vec<_>>(),
+        Err(_) => return streams,
+    };
+
+    supplies.sort();
+
+    for supply in supplies {
+        let path = power_supply_path.join(&supply);
+
+        match read_string(&path.join("type")).as_deref() {
+            Some("Battery") => streams.append(&mut battery_streams(&supply, &path)),
+            Some("Mains") => {
+                let online_path = path.join("online");
+                if read_number(&online_path).is_some() {
+                    streams.push(Stream::new(
+                        format!("{}Online", supply),
+                        format!("Whether AC adapter {} is connected (1) or not (0)", supply),
+                        move || read_number(&online_path),
+                        Some(0.0),
+                        Some(1.0),
+                        "",
+                        Some(1),
+                        0,
+                        false,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    streams
+}
+
+fn battery_streams(battery: &str, path: &Path) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
+
+    let mut details = Vec::new();
+    if let Some(model) = read_string(&path.join("model_name")) {
+        details.push(format!("model {}", model));
+    }
+    if let Some(health) = health(path) {
+        details.push(format!("health {:.0}%", health));
+    }
+
+    let details = if details.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", details.join(", "))
+    };
+
+    let capacity_path = path.join("capacity");
+    if read_number(&capacity_path).is_some() {
+        streams.push(Stream::new(
+            format!("{}Charge", battery),
+            format!("Charge level of battery {}{}", battery, details),
+            move || read_number(&capacity_path),
+            Some(0.0),
+            Some(100.0),
+            "%",
+            Some(3),
+            0,
+            false,
+        ));
+    }
+
+    if power(path).is_some() {
+        let path = PathBuf::from(path);
+        streams.push(Stream::new(
+            format!("{}Power", battery),
+            format!(
+                "Power flowing into (positive) or out of (negative) battery {}{}",
+                battery, details,
+            ),
+            move || power(&path),
+            None,
+            None,
+            "W",
+            Some(3),
+            1,
+            true,
+        ));
+    }
+
+    if energy(path).is_some() {
+        let path = PathBuf::from(path);
+        streams.push(Stream::new(
+            format!("{}Time", battery),
+            format!(
+                "Estimated time until battery {} is empty (when discharging) or full (when charging){}",
+                battery, details,
+            ),
+            move || time_remaining(&path),
+            Some(0.0),
+            None,
+            "h",
+            Some(3),
+            1,
+            false,
+        ));
+    }
+
+    streams
+}
+
+// Returns the current and full energy (or charge), and its rate of change
+fn energy(path: &Path) -> Option<(f64, f64, f64)> {
+    ENERGY_FILES.iter().find_map(|[now, full, _, rate]| {
+        Some((
+            read_number(&path.join(now))?,
+            read_number(&path.join(full))?,
+            // Some drivers report a negative rate while discharging
+            read_number(&path.join(rate))?.abs(),
+        ))
+    })
+}
+
+// Returns the capacity of the battery when full, as a percentage of its capacity when new
+fn health(path: &Path) -> Option<f64> {
+    ENERGY_FILES.iter().find_map(|[_, full, full_design, _]| {
+        let full_design = read_number(&path.join(full_design))?;
+        if full_design > 0.0 {
+            Some(read_number(&path.join(full))? / full_design * 100.0)
+        } else {
+            None
+        }
+    })
+}
+
+// Returns the power in watts, which is negative while the battery is discharging
+fn power(path: &Path) -> Option<f64> {
+    let watts = match read_number(&path.join("power_now")) {
+        Some(microwatts) => microwatts.abs() / 1e6,
+        None => {
+            let microamperes = read_number(&path.join("current_now"))?;
+            let microvolts = read_number(&path.join("voltage_now"))?;
+            (microamperes * microvolts).abs() / 1e12
+        }
+    };
+
+    if read_string(&path.join("status")).as_deref() == Some(DISCHARGING) {
+        Some(-watts)
+    } else {
+        Some(watts)
+    }
+}
+
+// Returns the estimated time in hours until the battery is empty or full,
+// or `None` if it is neither charging nor discharging
+fn time_remaining(path: &Path) -> Option<f64> {
+    let (now, full, rate) = energy(path)?;
+
+    if rate == 0.0 {
+        return None;
+    }
+
+    // Energy (or charge) divided by its rate of change is the time in hours
+    match read_string(&path.join("status")).as_deref() {
+        Some(DISCHARGING) => Some(now / rate),
+        Some(CHARGING) => Some((full - now).max(0.0) / rate),
+        _ => None,
+    }
+}
+
+fn read_number(path: &Path) -> Option<f64> {
+    read_string(path)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tests::fixture;
+
+    #[test]
+    fn test_power_supply_streams() {
+        let power_supply_path = fixture(
+            "power-supply",
+            &[
+                (
+                    "BAT0",
+                    vec![
+                        ("type", "Battery\n"),
+                        ("status", "Discharging\n"),
+                        ("model_name", "5B10W13930\n"),
+                        ("capacity", "80\n"),
+                        ("energy_now", "40000000\n"),
+                        ("energy_full", "50000000\n"),
+                        ("energy_full_design", "57000000\n"),
+                        ("power_now", "10000000\n"),
+                    ],
+                ),
+                ("AC", vec![("type", "Mains\n"), ("online", "0\n")]),
+                ("hidpp_battery_0", vec![("type", "Battery\n"), ("capacity", "55\n")]),
+            ],
+        );
+
+        let mut streams = power_supply_streams(&power_supply_path);
+        let values = streams.iter_mut().map(|s| s.value()).collect::<Vec<_>>();
+
+        assert_eq!(
+            streams.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec![
+                "ACOnline",
+                "BAT0Charge",
+                "BAT0Power",
+                "BAT0Time",
+                "hidpp_battery_0Charge"
+            ],
+        );
+        assert_eq!(
+            streams[1].description(),
+            "Charge level of battery BAT0 (model 5B10W13930, health 88%)",
+        );
+        assert_eq!(values, vec![Some(0.0), Some(80.0), Some(-10.0), Some(4.0), Some(55.0)],);
+    }
+}
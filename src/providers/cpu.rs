@@ -14,11 +14,40 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::io::{self, Error, ErrorKind};
+use std::fs;
+use std::io;
 
-use systemstat::{CPULoad, DelayedMeasurement, Platform, System};
+use systemstat::{DelayedMeasurement, Platform, System};
 
-use crate::stream::{Stream, StreamProvider};
+use crate::stream::{Source, Stream, StreamProvider};
+
+const STAT_PATH: &str = "/proc/stat";
+
+// Indices of fields in the CPU lines of `/proc/stat` (see
+// https://www.kernel.org/doc/Documentation/filesystems/proc.txt).
+// Guest time is not included because it is already counted as user time.
+const USER: usize = 0;
+const NICE: usize = 1;
+const SYSTEM: usize = 2;
+const IOWAIT: usize = 4;
+const IRQ: usize = 5;
+const SOFTIRQ: usize = 6;
+const STEAL: usize = 7;
+const FIELD_COUNT: usize = 8;
+
+// Name suffixes, descriptions, and fields of the CPU time breakdown streams
+const CPU_TIME_COMPONENTS: &[(&str, &str, &[usize])] = &[
+    ("User", "running in user mode", &[USER]),
+    ("Nice", "running low-priority (niced) processes in user mode", &[NICE]),
+    ("System", "running in kernel mode", &[SYSTEM]),
+    ("IRQ", "servicing interrupts", &[IRQ, SOFTIRQ]),
+    ("IOWait", "idle while waiting for I/O to complete", &[IOWAIT]),
+    (
+        "Steal",
+        "waiting while the hypervisor ran other virtual machines",
+        &[STEAL],
+    ),
+];
 
 pub struct CPUStreamProvider {}
 
@@ -26,24 +55,10 @@ impl StreamProvider for CPUStreamProvider {
     fn streams(&self) -> Vec<Box<dyn Stream>> {
         let mut streams = Vec::new();
 
-        let mut load: io::Result<DelayedMeasurement<CPULoad>> = Err(Error::new(ErrorKind::Other, ""));
-
         streams.push(Stream::new(
             "CPU",
             "Average utilization of all CPU cores during the past interval",
-            move || {
-                let value = if let Ok(ref load) = load {
-                    if let Ok(load) = load.done() {
-                        Some(f64::from((1.0 - load.idle) * 100.0))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
-                load = System::new().cpu_load_aggregate();
-                value
-            },
+            LoadSource::new(|| System::new().cpu_load_aggregate(), |load| load.idle),
             Some(0.0),
             Some(100.0),
             "%",
@@ -52,42 +67,173 @@ impl StreamProvider for CPUStreamProvider {
             false,
         ));
 
-        if let Ok(cpu) = System::new().cpu_load() {
-            if let Ok(cpu) = cpu.done() {
-                for i in 0..cpu.len() {
-                    let mut load: io::Result<DelayedMeasurement<Vec<CPULoad>>> = Err(Error::new(ErrorKind::Other, ""));
-
-                    streams.push(Stream::new(
-                        format!("Core{}", i + 1),
-                        format!("Utilization of CPU core {} during the past interval", i + 1),
-                        move || {
-                            let value = if let Ok(ref load) = load {
-                                if let Ok(load) = load.done() {
-                                    Some(f64::from((1.0 - load[i].idle) * 100.0))
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            };
-                            load = System::new().cpu_load();
-                            value
-                        },
-                        Some(0.0),
-                        Some(100.0),
-                        "%",
-                        Some(3),
-                        1,
-                        false,
-                    ));
-                }
-            }
+        streams.extend(cpu_time_streams("CPU", "all CPU cores", "cpu"));
+
+        for i in 0..core_count().unwrap_or(0) {
+            streams.push(Stream::new(
+                format!("Core{}", i + 1),
+                format!("Utilization of CPU core {} during the past interval", i + 1),
+                LoadSource::new(|| System::new().cpu_load(), move |load| load[i].idle),
+                Some(0.0),
+                Some(100.0),
+                "%",
+                Some(3),
+                1,
+                false,
+            ));
+
+            streams.extend(cpu_time_streams(
+                &format!("Core{}", i + 1),
+                &format!("CPU core {}", i + 1),
+                &format!("cpu{}", i),
+            ));
         }
 
         streams
     }
 }
 
+// Returns streams breaking down the time spent by the CPU (or core) with the given label
+// in `/proc/stat`. These are too detailed for most purposes and are inactive by default.
+fn cpu_time_streams(name: &str, description: &str, label: &str) -> Vec<Box<dyn Stream>> {
+    if cpu_times(label).is_none() {
+        return Vec::new();
+    }
+
+    CPU_TIME_COMPONENTS
+        .iter()
+        .map(|&(suffix, component_description, fields)| {
+            Stream::new(
+                format!("{}{}", name, suffix),
+                format!(
+                    "Percentage of time {} spent {} during the past interval",
+                    description, component_description,
+                ),
+                CPUTimeSource {
+                    label: String::from(label),
+                    fields,
+                    times: None,
+                },
+                Some(0.0),
+                Some(100.0),
+                "%",
+                Some(3),
+                1,
+                false,
+            )
+            .inactive_by_default()
+        })
+        .collect()
+}
+
+/// Returns the number of CPU cores, or `None` if it cannot be determined.
+pub fn core_count() -> Option<usize> {
+    System::new().cpu_load().ok()?.done().ok().map(|cpu| cpu.len())
+}
+
+// Computes CPU utilization from a measurement started at the previous call to `value`,
+// i.e. spanning the past interval
+struct LoadSource<T, F> {
+    measure: fn() -> io::Result<DelayedMeasurement<T>>,
+    idle: F,
+    load: Option<DelayedMeasurement<T>>,
+}
+
+impl<T, F> LoadSource<T, F>
+where
+    F: Fn(&T) -> f32,
+{
+    fn new(measure: fn() -> io::Result<DelayedMeasurement<T>>, idle: F) -> Self {
+        LoadSource {
+            measure,
+            idle,
+            load: None,
+        }
+    }
+}
+
+impl<T, F> Source for LoadSource<T, F>
+where
+    F: Fn(&T) -> f32 + Send,
+{
+    fn value(&mut self) -> Option<f64> {
+        let value = match self.load {
+            Some(ref load) => load
+                .done()
+                .ok()
+                .map(|load| f64::from((1.0 - (self.idle)(&load)) * 100.0)),
+            None => None,
+        };
+        self.load = (self.measure)().ok();
+        value
+    }
+
+    fn reset(&mut self) {
+        self.load = None;
+    }
+}
+
+// Computes the percentage of CPU time spent in the given states
+// since the previous call to `value`, i.e. during the past interval
+struct CPUTimeSource {
+    label: String,
+    fields: &'static [usize],
+    times: Option<Vec<u64>>,
+}
+
+impl Source for CPUTimeSource {
+    fn value(&mut self) -> Option<f64> {
+        let times = cpu_times(&self.label);
+
+        let value = match (&self.times, &times) {
+            (Some(last_times), Some(times)) => cpu_time_percentage(last_times, times, self.fields),
+            _ => None,
+        };
+
+        self.times = times;
+        value
+    }
+
+    fn reset(&mut self) {
+        self.times = None;
+    }
+}
+
+fn cpu_time_percentage(last_times: &[u64], times: &[u64], fields: &[usize]) -> Option<f64> {
+    // Some counters (notably iowait) can decrease
+    let delta = |i: usize| times[i].saturating_sub(last_times[i]) as f64;
+
+    let total = (0..FIELD_COUNT).map(delta).sum::<f64>();
+
+    if total > 0.0 {
+        Some(fields.iter().map(|&i| delta(i)).sum::<f64>() / total * 100.0)
+    } else {
+        None
+    }
+}
+
+fn cpu_times(label: &str) -> Option<Vec<u64>> {
+    parse_cpu_times(&fs::read_to_string(STAT_PATH).ok()?, label)
+}
+
+// Extracts the times spent in each state by the CPU with the given label
+// from the contents of `/proc/stat`
+fn parse_cpu_times(stat: &str, label: &str) -> Option<Vec<u64>> {
+    let line = stat.lines().find(|l| l.split_whitespace().next() == Some(label))?;
+
+    let mut times = line
+        .split_whitespace()
+        .skip(1)
+        .take(FIELD_COUNT)
+        .map(|t| t.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+
+    // Older kernels don't report all fields
+    times.resize(FIELD_COUNT, 0);
+
+    Some(times)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,4 +243,21 @@ mod tests {
         let streams = CPUStreamProvider {}.streams();
         assert!(!streams.is_empty());
     }
+
+    #[test]
+    fn test_cpu_times() {
+        let stat = "cpu  100 0 50 800 10 5 5 30 0 0\ncpu0 60 0 30 400 5 2 3 0 0 0\ncpu1 40 0 20 400\n";
+
+        let last_times = parse_cpu_times(stat, "cpu").unwrap();
+        assert_eq!(last_times, vec![100, 0, 50, 800, 10, 5, 5, 30]);
+        assert_eq!(parse_cpu_times(stat, "cpu1").unwrap(), vec![40, 0, 20, 400, 0, 0, 0, 0]);
+        assert!(parse_cpu_times(stat, "cpu2").is_none());
+
+        let times = vec![150, 0, 50, 900, 5, 10, 10, 70];
+        assert_eq!(cpu_time_percentage(&last_times, &times, &[USER]), Some(25.0));
+        assert_eq!(cpu_time_percentage(&last_times, &times, &[IRQ, SOFTIRQ]), Some(5.0));
+        assert_eq!(cpu_time_percentage(&last_times, &times, &[STEAL]), Some(20.0));
+        assert_eq!(cpu_time_percentage(&last_times, &times, &[IOWAIT]), Some(0.0));
+        assert_eq!(cpu_time_percentage(&times, &times, &[USER]), None);
+    }
 }
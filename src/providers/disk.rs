@@ -0,0 +1,178 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use crate::providers::{rate_calculator, Percentage};
+use crate::stream::{Source, Stream, StreamProvider};
+
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+const BLOCK_DEVICES_PATH: &str = "/sys/block";
+
+// Devices backed by files or memory rather than storage hardware
+const IGNORED_DEVICE_PREFIXES: [&str; 3] = ["loop", "ram", "zram"];
+
+// Indices of fields in the statistics for a device (see
+// https://www.kernel.org/doc/Documentation/ABI/testing/procfs-diskstats)
+const READS_COMPLETED: usize = 0;
+const SECTORS_READ: usize = 2;
+const WRITES_COMPLETED: usize = 4;
+const SECTORS_WRITTEN: usize = 6;
+const TIME_DOING_IO: usize = 9;
+
+// Statistics are reported in units of 512 bytes, regardless of the device's actual sector size
+const SECTOR_SIZE: f64 = 512.0;
+
+pub struct DiskStreamProvider {}
+
+impl StreamProvider for DiskStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        // Only whole devices are listed here, not partitions
+        let mut devices = match fs::read_dir(BLOCK_DEVICES_PATH) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|d| !IGNORED_DEVICE_PREFIXES.iter().any(|p| d.starts_with(p)))
+                .collect::<Vec<_>>(),
+            Err(_) => return streams,
+        };
+
+        devices.sort();
+
+        for device in devices {
+            if stats(&device).is_none() {
+                continue;
+            }
+
+            streams.push(
+                Stream::new(
+                    format!("{}Read", device),
+                    format!("Read throughput of {} during the past interval", device),
+                    counter(&device, SECTORS_READ, SECTOR_SIZE),
+                    Some(0.0),
+                    None,
+                    "B",
+                    None,
+                    1,
+                    false,
+                )
+                .as_rate(),
+            );
+            streams.push(
+                Stream::new(
+                    format!("{}Write", device),
+                    format!("Write throughput of {} during the past interval", device),
+                    counter(&device, SECTORS_WRITTEN, SECTOR_SIZE),
+                    Some(0.0),
+                    None,
+                    "B",
+                    None,
+                    1,
+                    false,
+                )
+                .as_rate(),
+            );
+            streams.push(
+                Stream::new(
+                    format!("{}ReadOps", device),
+                    format!("Read operations per second on {} during the past interval", device),
+                    counter(&device, READS_COMPLETED, 1.0),
+                    Some(0.0),
+                    None,
+                    "",
+                    None,
+                    1,
+                    false,
+                )
+                .as_rate(),
+            );
+            streams.push(
+                Stream::new(
+                    format!("{}WriteOps", device),
+                    format!("Write operations per second on {} during the past interval", device),
+                    counter(&device, WRITES_COMPLETED, 1.0),
+                    Some(0.0),
+                    None,
+                    "",
+                    None,
+                    1,
+                    false,
+                )
+                .as_rate(),
+            );
+            streams.push(Stream::new(
+                format!("{}Util", device),
+                format!("Percentage of time {} was busy during the past interval", device),
+                // Milliseconds spent doing I/O per second, divided by 1000 milliseconds
+                // and multiplied by 100 percent
+                Percentage(counter(&device, TIME_DOING_IO, 0.1)),
+                Some(0.0),
+                Some(100.0),
+                "%",
+                Some(3),
+                1,
+                false,
+            ));
+        }
+
+        streams
+    }
+}
+
+// Returns a source for the rate of change per second of the given field, multiplied by `factor`
+fn counter(device: &str, field: usize, factor: f64) -> impl Source {
+    let device = String::from(device);
+    rate_calculator(move || stats(&device).map(|s| s[field] as f64 * factor))
+}
+
+fn stats(device: &str) -> Option<Vec<u64>> {
+    parse_stats(&fs::read_to_string(DISKSTATS_PATH).ok()?, device)
+}
+
+// Extracts the statistics for the given device from the contents of `/proc/diskstats`
+fn parse_stats(diskstats: &str, device: &str) -> Option<Vec<u64>> {
+    for line in diskstats.lines() {
+        let mut fields = line.split_whitespace().skip(2);
+
+        if fields.next() == Some(device) {
+            let stats = fields.map(|f| f.parse().ok()).collect::<Option<Vec<u64>>>()?;
+            return if stats.len() > TIME_DOING_IO { Some(stats) } else { None };
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        let diskstats = "   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0\n \
+                         254       0 vda 6213 4508 2091050 6290 4279 6525 2849520 5303 0 3132 12134\n \
+                         254       1 vda1 6000 4000 2000000 6000 4000 6000 2800000 5000 0 3000 12000\n";
+
+        let stats = parse_stats(diskstats, "vda").unwrap();
+        assert_eq!(stats[SECTORS_READ], 2091050);
+        assert_eq!(stats[WRITES_COMPLETED], 4279);
+        assert_eq!(stats[TIME_DOING_IO], 3132);
+
+        assert!(parse_stats(diskstats, "vdb").is_none());
+    }
+}
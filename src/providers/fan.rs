@@ -34,7 +34,7 @@ impl StreamProvider for FanStreamProvider {
             streams.push(Stream::new(
                 format!("{}Fan", name),
                 format!("Fan speed (feature {} on chip {})", feature_label, chip_name),
-                move || subfeature.get_value().ok(),
+                move || subfeature.value(),
                 None,
                 None,
                 "RPM",
@@ -0,0 +1,119 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use systemstat::{Platform, System};
+
+use crate::stream::{Stream, StreamProvider};
+
+// Filesystems that don't represent persistent storage
+// (or, in the case of SquashFS, are always full)
+const PSEUDO_FILESYSTEM_TYPES: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "overlay",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "squashfs",
+    "sysfs",
+    "tmpfs",
+    "tracefs",
+];
+
+pub struct FilesystemStreamProvider {}
+
+impl StreamProvider for FilesystemStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        if let Ok(mut filesystems) = System::new().mounts() {
+            filesystems.sort_by(|a, b| a.fs_mounted_on.cmp(&b.fs_mounted_on));
+
+            // The same filesystem can be mounted multiple times
+            let mut sources = HashSet::new();
+
+            for filesystem in filesystems {
+                if PSEUDO_FILESYSTEM_TYPES.contains(&filesystem.fs_type.as_str())
+                    || filesystem.total.as_u64() == 0
+                    || !sources.insert(filesystem.fs_mounted_from.clone())
+                {
+                    continue;
+                }
+
+                let mount_point = filesystem.fs_mounted_on.clone();
+
+                // Space reserved for privileged processes (e.g. 5% by default on ext4)
+                // can't be used by others, so the filesystem is full once `avail` reaches zero
+                let reserved = filesystem.free.as_u64().saturating_sub(filesystem.avail.as_u64());
+                let capacity = filesystem.total.as_u64().saturating_sub(reserved);
+
+                streams.push(
+                    Stream::new(
+                        format!("FS {}", filesystem.fs_mounted_on),
+                        format!(
+                            "Space used on {} ({}) mounted at {}",
+                            filesystem.fs_mounted_from, filesystem.fs_type, filesystem.fs_mounted_on,
+                        ),
+                        move || {
+                            System::new()
+                                .mount_at(&mount_point)
+                                .ok()
+                                .map(|fs| capacity.saturating_sub(fs.avail.as_u64()) as f64)
+                        },
+                        Some(0.0),
+                        Some(capacity as f64),
+                        "B",
+                        None,
+                        1,
+                        false,
+                    )
+                    .with_forecast(),
+                );
+            }
+        }
+
+        streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem_stream_provider() {
+        let streams = FilesystemStreamProvider {}.streams();
+        assert!(!streams.is_empty());
+        assert!(streams.iter().all(|s| s.forecast() && s.max().is_some()));
+    }
+}
@@ -0,0 +1,169 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::providers::read_string;
+use crate::stream::{Stream, StreamProvider};
+
+const CPU_PATH: &str = "/sys/devices/system/cpu";
+
+pub struct FrequencyStreamProvider {}
+
+impl StreamProvider for FrequencyStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        frequency_streams(Path::new(CPU_PATH))
+    }
+}
+
+fn frequency_streams(cpu_path: &Path) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
+
+    let mut cpus = match fs::read_dir(cpu_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| name.starts_with("cpu"))
+            .filter_map(|name| name.trim_start_matches("cpu").parse::<usize>().ok())
+            .collect::<Vec<_>>(),
+        Err(_) => return streams,
+    };
+
+    cpus.sort();
+
+    // Logical CPUs that share a physical core (hyperthreads) also share its clock,
+    // so there is one stream per physical core. Cores are numbered in the order
+    // of their package and core IDs, matching the numbering of the `Core<N>Temp` streams.
+    let mut cores = BTreeMap::new();
+
+    for cpu in cpus {
+        let cpu_path = cpu_path.join(format!("cpu{}", cpu));
+
+        if frequency(&cpu_path.join("cpufreq").join("scaling_cur_freq")).is_none() {
+            continue;
+        }
+
+        let topology_id = |name| {
+            read_string(&cpu_path.join("topology").join(name))?
+                .parse::<usize>()
+                .ok()
+        };
+
+        let core = match (topology_id("physical_package_id"), topology_id("core_id")) {
+            (Some(package_id), Some(core_id)) => (package_id, core_id),
+            _ => (0, cpu),
+        };
+
+        cores.entry(core).or_insert(cpu_path);
+    }
+
+    for (i, cpu_path) in cores.into_values().enumerate() {
+        let cpufreq_path = cpu_path.join("cpufreq");
+        let current_path = cpufreq_path.join("scaling_cur_freq");
+
+        let description = match read_string(&cpufreq_path.join("scaling_governor")) {
+            Some(governor) => format!("Clock frequency of CPU core {} (governor: {})", i + 1, governor),
+            None => format!("Clock frequency of CPU core {}", i + 1),
+        };
+
+        let stream = Stream::new(
+            format!("Core{}Freq", i + 1),
+            description,
+            move || frequency(&current_path),
+            frequency(&cpufreq_path.join("cpuinfo_min_freq")),
+            None,
+            "Hz",
+            None,
+            2,
+            false,
+        );
+
+        // Boost frequencies can exceed the nominal maximum
+        streams.push(match frequency(&cpufreq_path.join("cpuinfo_max_freq")) {
+            Some(max_frequency) => stream.with_soft_max(max_frequency),
+            None => stream,
+        });
+    }
+
+    streams
+}
+
+// Reads a frequency in kHz and returns it in Hz
+fn frequency(path: &Path) -> Option<f64> {
+    read_string(path)?.parse::<f64>().ok().map(|khz| khz * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tests::fixture;
+
+    #[test]
+    fn test_frequency_streams() {
+        let cpu_path = fixture(
+            "cpu",
+            &[
+                (
+                    "cpu0",
+                    vec![
+                        ("cpufreq/scaling_cur_freq", "2400000\n"),
+                        ("cpufreq/cpuinfo_min_freq", "800000\n"),
+                        ("cpufreq/cpuinfo_max_freq", "3600000\n"),
+                        ("cpufreq/scaling_governor", "powersave\n"),
+                        ("topology/physical_package_id", "0\n"),
+                        ("topology/core_id", "0\n"),
+                    ],
+                ),
+                (
+                    "cpu1",
+                    vec![
+                        ("cpufreq/scaling_cur_freq", "1200000\n"),
+                        ("topology/physical_package_id", "0\n"),
+                        ("topology/core_id", "4\n"),
+                    ],
+                ),
+                // Hyperthread sharing the first core
+                (
+                    "cpu2",
+                    vec![
+                        ("cpufreq/scaling_cur_freq", "2400000\n"),
+                        ("topology/physical_package_id", "0\n"),
+                        ("topology/core_id", "0\n"),
+                    ],
+                ),
+                ("cpufreq", vec![]),
+            ],
+        );
+
+        let mut streams = frequency_streams(&cpu_path);
+        let value = streams[0].value();
+
+        assert_eq!(
+            streams.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec!["Core1Freq", "Core2Freq"],
+        );
+        assert_eq!(
+            streams[0].description(),
+            "Clock frequency of CPU core 1 (governor: powersave)"
+        );
+        assert_eq!((streams[0].min(), streams[0].max()), (Some(800e6), None));
+        assert_eq!(streams[0].soft_max(), Some(3600e6));
+        assert_eq!((streams[1].min(), streams[1].soft_max()), (None, None));
+        assert_eq!(value, Some(2400e6));
+    }
+}
@@ -0,0 +1,102 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use systemstat::{LoadAverage, Platform, System};
+
+use crate::providers::cpu::core_count;
+use crate::providers::parse_value;
+use crate::stream::{Stream, StreamProvider};
+
+const STAT_PATH: &str = "/proc/stat";
+const PROCS_RUNNING: &str = "procs_running";
+const PROCS_BLOCKED: &str = "procs_blocked";
+
+// Selects one of the load averages
+type LoadAverageField = fn(&LoadAverage) -> f32;
+
+pub struct LoadStreamProvider {}
+
+impl StreamProvider for LoadStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        // A system is fully loaded when there is one runnable task for each core
+        let cores = core_count().unwrap_or(1) as f64;
+
+        if System::new().load_average().is_ok() {
+            let averages: [(&str, &str, LoadAverageField); 3] = [
+                ("Load1", "minute", |load| load.one),
+                ("Load5", "5 minutes", |load| load.five),
+                ("Load15", "15 minutes", |load| load.fifteen),
+            ];
+
+            for &(name, period, average) in &averages {
+                streams.push(
+                    Stream::new(
+                        name,
+                        format!("Average number of runnable or waiting tasks over the past {}", period),
+                        move || System::new().load_average().ok().map(|load| f64::from(average(&load))),
+                        Some(0.0),
+                        None,
+                        "",
+                        Some(3),
+                        2,
+                        false,
+                    )
+                    .with_soft_max(cores),
+                );
+            }
+        }
+
+        for &(name, key, description) in &[
+            (
+                "TasksRunning",
+                PROCS_RUNNING,
+                "Number of tasks currently running or ready to run",
+            ),
+            (
+                "TasksBlocked",
+                PROCS_BLOCKED,
+                "Number of tasks currently blocked waiting for I/O",
+            ),
+        ] {
+            if stat(key).is_some() {
+                streams.push(
+                    Stream::new(
+                        name,
+                        description,
+                        move || stat(key),
+                        Some(0.0),
+                        None,
+                        "",
+                        Some(4),
+                        0,
+                        false,
+                    )
+                    .with_soft_max(cores),
+                );
+            }
+        }
+
+        streams
+    }
+}
+
+fn stat(key: &str) -> Option<f64> {
+    parse_value(&fs::read_to_string(STAT_PATH).ok()?, key)
+}
@@ -14,63 +14,138 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use systemstat::{Platform, System};
+use std::collections::BTreeMap;
+
+use systemstat::{ByteSize, Platform, System};
 
 use crate::stream::{Stream, StreamProvider};
 
+const MEM_TOTAL: &str = "MemTotal";
+const MEM_FREE: &str = "MemFree";
+const MEM_AVAILABLE: &str = "MemAvailable";
 const SWAP_TOTAL: &str = "SwapTotal";
 const SWAP_FREE: &str = "SwapFree";
 
+// Keys in `/proc/meminfo`, names, and descriptions of the detailed memory streams
+const MEMORY_DETAILS: &[(&str, &str, &str)] = &[
+    ("Cached", "MemCached", "Amount of memory used by the page cache"),
+    ("Buffers", "MemBuffers", "Amount of memory used by block device buffers"),
+    ("Shmem", "MemShared", "Amount of memory used by shared memory and tmpfs"),
+    (
+        "Dirty",
+        "MemDirty",
+        "Amount of memory waiting to be written back to disk",
+    ),
+    (
+        "Writeback",
+        "MemWriteback",
+        "Amount of memory currently being written back to disk",
+    ),
+    (
+        "Slab",
+        "MemSlab",
+        "Amount of memory used by kernel data structure caches",
+    ),
+    (
+        "AnonHugePages",
+        "MemAnonHugePages",
+        "Amount of memory used by transparent huge pages",
+    ),
+];
+
 pub struct MemoryStreamProvider {}
 
 impl StreamProvider for MemoryStreamProvider {
     fn streams(&self) -> Vec<Box<dyn Stream>> {
-        let mut streams = Vec::new();
-
-        if let Ok(memory) = System::new().memory() {
-            streams.push(Stream::new(
-                "Mem",
-                "Amount of physical memory (RAM) in use",
-                move || {
-                    if let Ok(memory) = System::new().memory() {
-                        Some((memory.total.as_u64() - memory.free.as_u64()) as f64)
-                    } else {
-                        None
-                    }
-                },
-                Some(0.0),
-                Some(memory.total.as_u64() as f64),
-                "B",
-                None,
-                1,
-                false,
-            ));
-
-            let meminfo = memory.platform_memory.meminfo;
-            if meminfo.contains_key(SWAP_TOTAL) && meminfo.contains_key(SWAP_FREE) {
-                streams.push(Stream::new(
-                    "Swap",
-                    "Amount of swap space in use",
-                    move || {
-                        if let Ok(memory) = System::new().memory() {
-                            let meminfo = memory.platform_memory.meminfo;
-                            Some((meminfo[SWAP_TOTAL].as_u64() - meminfo[SWAP_FREE].as_u64()) as f64)
-                        } else {
-                            None
-                        }
-                    },
+        memory_streams(|| System::new().memory().ok().map(|memory| memory.platform_memory.meminfo))
+    }
+}
+
+// Returns the memory streams, reading the contents of `/proc/meminfo` from `meminfo`
+fn memory_streams<F>(meminfo: F) -> Vec<Box<dyn Stream>>
+where
+    F: Fn() -> Option<BTreeMap<String, ByteSize>> + Clone + Send + 'static,
+{
+    let mut streams = Vec::new();
+
+    let initial_meminfo = match meminfo() {
+        Some(initial_meminfo) => initial_meminfo,
+        None => return streams,
+    };
+
+    let total = match initial_meminfo.get(MEM_TOTAL) {
+        Some(total) => total.as_u64() as f64,
+        None => return streams,
+    };
+
+    let source = meminfo.clone();
+    streams.push(Stream::new(
+        "Mem",
+        "Amount of physical memory (RAM) in use, excluding caches that can be reclaimed",
+        move || source().and_then(|meminfo| memory_used(&meminfo)),
+        Some(0.0),
+        Some(total),
+        "B",
+        None,
+        1,
+        false,
+    ));
+
+    for &(key, name, description) in MEMORY_DETAILS {
+        if initial_meminfo.contains_key(key) {
+            let source = meminfo.clone();
+            streams.push(
+                Stream::new(
+                    name,
+                    description,
+                    move || source().and_then(|meminfo| meminfo.get(key).map(|value| value.as_u64() as f64)),
                     Some(0.0),
-                    Some(meminfo[SWAP_TOTAL].as_u64() as f64),
+                    Some(total),
                     "B",
                     None,
                     1,
                     false,
-                ));
-            }
+                )
+                .inactive_by_default(),
+            );
         }
+    }
 
-        streams
+    if let Some(swap_total) = initial_meminfo
+        .get(SWAP_TOTAL)
+        .filter(|_| initial_meminfo.contains_key(SWAP_FREE))
+    {
+        streams.push(Stream::new(
+            "Swap",
+            "Amount of swap space in use",
+            move || {
+                let meminfo = meminfo()?;
+                Some(
+                    meminfo
+                        .get(SWAP_TOTAL)?
+                        .as_u64()
+                        .saturating_sub(meminfo.get(SWAP_FREE)?.as_u64()) as f64,
+                )
+            },
+            Some(0.0),
+            Some(swap_total.as_u64() as f64),
+            "B",
+            None,
+            1,
+            false,
+        ));
     }
+
+    streams
+}
+
+// Returns the amount of memory in use. Free memory doesn't include caches,
+// which are released when applications need the memory, so the memory
+// that is available to applications is used instead where possible.
+fn memory_used(meminfo: &BTreeMap<String, ByteSize>) -> Option<f64> {
+    let total = meminfo.get(MEM_TOTAL)?.as_u64();
+    let available = meminfo.get(MEM_AVAILABLE).or_else(|| meminfo.get(MEM_FREE))?.as_u64();
+    Some(total.saturating_sub(available) as f64)
 }
 
 #[cfg(test)]
@@ -82,4 +157,38 @@ mod tests {
         let streams = MemoryStreamProvider {}.streams();
         assert!(!streams.is_empty());
     }
+
+    #[test]
+    fn test_memory_streams() {
+        let meminfo = [
+            ("MemTotal", 1000),
+            ("MemFree", 100),
+            ("MemAvailable", 600),
+            ("Cached", 300),
+            ("Dirty", 20),
+        ]
+        .iter()
+        .map(|&(key, value)| (String::from(key), ByteSize::b(value)))
+        .collect::<BTreeMap<_, _>>();
+
+        let source = meminfo.clone();
+        let mut streams = memory_streams(move || Some(source.clone()));
+
+        assert_eq!(
+            streams.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec!["Mem", "MemCached", "MemDirty"],
+        );
+        assert!(streams.iter().all(|s| s.max() == Some(1000.0)));
+        assert!(streams[0].active_by_default());
+        assert!(!streams[1].active_by_default() && !streams[2].active_by_default());
+
+        assert_eq!(streams[0].value(), Some(400.0));
+        assert_eq!(streams[1].value(), Some(300.0));
+        assert_eq!(streams[2].value(), Some(20.0));
+
+        // Kernels older than 3.14 don't report the available memory
+        let mut meminfo = meminfo;
+        meminfo.remove("MemAvailable");
+        assert_eq!(memory_used(&meminfo), Some(900.0));
+    }
 }
@@ -14,51 +14,536 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod battery;
 mod cpu;
+mod disk;
 mod fan;
+mod filesystem;
+mod frequency;
+mod load;
 mod memory;
 mod network;
+mod power;
+mod pressure;
+mod process;
+mod rapl;
 mod temperature;
+mod vmstat;
 
-use sensors::{FeatureType, Sensors, Subfeature, SubfeatureType};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Instant;
 
+use crossbeam_channel::{self, Sender};
+use regex::Regex;
+use sensors::FeatureType::{self, SENSORS_FEATURE_CURR, SENSORS_FEATURE_IN};
+use sensors::SubfeatureType::{self, SENSORS_SUBFEATURE_CURR_INPUT, SENSORS_SUBFEATURE_IN_INPUT};
+use sensors::{Sensors, Subfeature};
+
+use self::battery::BatteryStreamProvider;
 use self::cpu::CPUStreamProvider;
+use self::disk::DiskStreamProvider;
 use self::fan::FanStreamProvider;
+use self::filesystem::FilesystemStreamProvider;
+use self::frequency::FrequencyStreamProvider;
+use self::load::LoadStreamProvider;
 use self::memory::MemoryStreamProvider;
 use self::network::BandwidthStreamProvider;
+use self::power::PowerStreamProvider;
+use self::pressure::PressureStreamProvider;
+use self::process::ProcessStreamProvider;
+use self::rapl::RAPLStreamProvider;
 use self::temperature::TemperatureStreamProvider;
-use crate::stream::{Stream, StreamProvider};
+use self::vmstat::VMStatStreamProvider;
+use crate::stream::{Source, Stream, StreamProvider};
 
-pub fn streams() -> Vec<Box<dyn Stream>> {
+/// Returns the streams from all providers. Loopback and container interfaces
+/// are left out of the network streams if `exclude_virtual_interfaces` is `true`.
+pub fn streams(exclude_virtual_interfaces: bool) -> Vec<Box<dyn Stream>> {
     let providers: Vec<Box<dyn StreamProvider>> = vec![
         Box::new(CPUStreamProvider {}),
+        Box::new(LoadStreamProvider {}),
+        Box::new(PressureStreamProvider {}),
         Box::new(MemoryStreamProvider {}),
+        Box::new(VMStatStreamProvider {}),
         Box::new(TemperatureStreamProvider {}),
         Box::new(FanStreamProvider {}),
-        Box::new(BandwidthStreamProvider {}),
+        Box::new(SensorStreamProvider {
+            feature_type: SENSORS_FEATURE_IN,
+            subfeature_type: SENSORS_SUBFEATURE_IN_INPUT,
+            suffix: "Voltage",
+            unit: "V",
+            description: "Voltage",
+        }),
+        Box::new(SensorStreamProvider {
+            feature_type: SENSORS_FEATURE_CURR,
+            subfeature_type: SENSORS_SUBFEATURE_CURR_INPUT,
+            suffix: "Current",
+            unit: "A",
+            description: "Electric current",
+        }),
+        Box::new(PowerStreamProvider {}),
+        Box::new(RAPLStreamProvider {}),
+        Box::new(BatteryStreamProvider {}),
+        Box::new(FrequencyStreamProvider {}),
+        Box::new(BandwidthStreamProvider {
+            exclude_virtual_interfaces,
+        }),
+        Box::new(DiskStreamProvider {}),
+        Box::new(FilesystemStreamProvider {}),
     ];
 
-    providers.iter().flat_map(|p| p.streams()).collect()
+    group_core_streams(providers.iter().flat_map(|p| p.streams()).collect())
+}
+
+/// Returns streams for the process with the given PID, which return no values
+/// once the process has exited. Returns no streams if there is no such process.
+pub fn process_streams(pid: u32) -> Vec<Box<dyn Stream>> {
+    ProcessStreamProvider { pid }.streams()
+}
+
+// Moves all streams relating to the same CPU core (e.g. `Core2`, `Core2Temp`, `Core2Freq`)
+// next to each other, even if they come from different providers
+fn group_core_streams(streams: Vec<Box<dyn Stream>>) -> Vec<Box<dyn Stream>> {
+    let regex = Regex::new(r"^Core(\d+)(\D|$)").unwrap();
+
+    let mut group_indices = HashMap::new();
+
+    let mut streams = streams
+        .into_iter()
+        .enumerate()
+        .map(|(i, stream)| {
+            let group_index = match regex.captures(&stream.name()) {
+                Some(captures) => *group_indices.entry(String::from(&captures[1])).or_insert(i),
+                None => i,
+            };
+            ((group_index, i), stream)
+        })
+        .collect::<Vec<_>>();
+
+    streams.sort_by_key(|&(key, _)| key);
+
+    streams.into_iter().map(|(_, stream)| stream).collect()
+}
+
+// Returns the name followed by the suffix, with a separator and a number inserted
+// between them if that name is already taken (e.g. by an identical sensor on another chip).
+// The separator keeps the number apart from names that end in digits themselves.
+fn unique_name(name: &str, suffix: &str, names: &mut HashSet<String>) -> String {
+    let mut unique_name = format!("{}{}", name, suffix);
+    let mut number = 1;
+
+    while !names.insert(unique_name.clone()) {
+        number += 1;
+        unique_name = format!("{}_{}{}", name, number, suffix);
+    }
+
+    unique_name
 }
 
-fn subfeatures(feature_type: FeatureType, subfeature_type: SubfeatureType) -> Vec<(Subfeature, String, String)> {
-    let mut subfeatures = Vec::new();
+// Returns the contents of the given file with surrounding whitespace removed,
+// for reading single values from sysfs
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| String::from(s.trim()))
+}
+
+// Provides the streams of a type of libsensors readings
+// that need no further processing (e.g. voltages)
+struct SensorStreamProvider {
+    feature_type: FeatureType,
+    subfeature_type: SubfeatureType,
+    suffix: &'static str,
+    unit: &'static str,
+    description: &'static str,
+}
+
+impl StreamProvider for SensorStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        sensor_streams(
+            self.feature_type,
+            self.subfeature_type,
+            self.suffix,
+            self.unit,
+            self.description,
+        )
+    }
+}
+
+// Returns a stream for each subfeature of the given type, named after
+// its feature's label followed by the suffix
+fn sensor_streams(
+    feature_type: FeatureType,
+    subfeature_type: SubfeatureType,
+    suffix: &str,
+    unit: &str,
+    description: &str,
+) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
 
-    for chip in Sensors::new() {
-        if let Ok(chip_name) = chip.get_name() {
-            for feature in chip {
-                if *feature.feature_type() == feature_type {
-                    if let Ok(feature_label) = feature.get_label() {
-                        for subfeature in feature {
-                            if *subfeature.subfeature_type() == subfeature_type {
-                                subfeatures.push((subfeature, feature_label.clone(), chip_name.clone()));
+    let mut names = HashSet::new();
+
+    for (subfeature, feature_label, chip_name) in subfeatures(feature_type, subfeature_type) {
+        streams.push(Stream::new(
+            unique_name(&feature_label.replace(" ", ""), suffix, &mut names),
+            format!("{} (feature {} on chip {})", description, feature_label, chip_name),
+            move || subfeature.value(),
+            None,
+            None,
+            unit,
+            Some(3),
+            2,
+            true,
+        ));
+    }
+
+    streams
+}
+
+// Extracts the value with the given key from the contents of a file
+// consisting of lines of the form `key value` (e.g. `/proc/stat` or `/proc/vmstat`)
+fn parse_value(contents: &str, key: &str) -> Option<f64> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        if fields.next() == Some(key) {
+            fields.next().and_then(|value| value.parse().ok())
+        } else {
+            None
+        }
+    })
+}
+
+fn subfeatures(feature_type: FeatureType, subfeature_type: SubfeatureType) -> Vec<(SubfeatureId, String, String)> {
+    with_sensors(move |resolved_subfeatures| {
+        let mut subfeatures = Vec::new();
+
+        for chip in Sensors::new() {
+            if let Ok(chip_name) = chip.get_name() {
+                for feature in chip {
+                    if *feature.feature_type() == feature_type {
+                        if let Ok(feature_label) = feature.get_label() {
+                            let feature_name = String::from(feature.name());
+                            for subfeature in feature {
+                                if *subfeature.subfeature_type() == subfeature_type {
+                                    subfeatures.push((
+                                        SubfeatureId {
+                                            chip_name: chip_name.clone(),
+                                            feature_name: feature_name.clone(),
+                                            index: resolved_subfeatures.len(),
+                                        },
+                                        feature_label.clone(),
+                                        chip_name.clone(),
+                                    ));
+                                    resolved_subfeatures.push(subfeature);
+                                }
                             }
                         }
                     }
                 }
             }
         }
+
+        subfeatures
+    })
+}
+
+type SensorsJob = Box<dyn FnOnce(&mut Vec<Subfeature>) + Send>;
+
+// Runs the given function on a thread of its own, which owns all subfeatures
+// that have been resolved so far. libsensors isn't safe to use from several
+// threads at once, and its subfeatures cannot be sent to other threads,
+// so the streams' values are read on that thread as well.
+fn with_sensors<F, T>(function: F) -> T
+where
+    F: FnOnce(&mut Vec<Subfeature>) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    static JOBS: OnceLock<Sender<SensorsJob>> = OnceLock::new();
+
+    let jobs = JOBS.get_or_init(|| {
+        let (jobs, job_receiver) = crossbeam_channel::unbounded::<SensorsJob>();
+
+        thread::spawn(move || {
+            let mut subfeatures = Vec::new();
+            for job in job_receiver.iter() {
+                job(&mut subfeatures);
+            }
+        });
+
+        jobs
+    });
+
+    let (result_sender, result) = crossbeam_channel::bounded(1);
+
+    jobs.send(Box::new(move |subfeatures: &mut Vec<Subfeature>| {
+        result_sender.send(function(subfeatures)).ok();
+    }))
+    .unwrap();
+
+    result.recv().unwrap()
+}
+
+// Identifies a libsensors subfeature that has been resolved by `subfeatures`.
+// Unlike `Subfeature` itself, this can be sent to the threads that sample the streams.
+#[derive(Clone)]
+struct SubfeatureId {
+    chip_name: String,
+    // Name of the feature the subfeature belongs to
+    feature_name: String,
+    // Index of the subfeature among the resolved subfeatures
+    index: usize,
+}
+
+impl SubfeatureId {
+    fn value(&self) -> Option<f64> {
+        let index = self.index;
+        with_sensors(move |subfeatures| subfeatures[index].get_value().ok())
+    }
+
+    // Returns the value of the subfeature of the given type
+    // that belongs to the same feature as this subfeature
+    fn sibling_value(&self, subfeature_type: SubfeatureType) -> Option<f64> {
+        let chip_name = self.chip_name.clone();
+        let feature_name = self.feature_name.clone();
+
+        with_sensors(move |_| {
+            for chip in Sensors::new() {
+                if chip.get_name().ok().as_ref() == Some(&chip_name) {
+                    for feature in chip {
+                        if feature.name() == feature_name {
+                            return feature.get_subfeature(subfeature_type)?.get_value().ok();
+                        }
+                    }
+                }
+            }
+
+            None
+        })
+    }
+}
+
+fn rate_calculator<F>(value: F) -> RateCalculator<F>
+where
+    F: FnMut() -> Option<f64> + Send + 'static,
+{
+    RateCalculator {
+        value,
+        range: None,
+        last_time: Instant::now(),
+        last_input: None,
+    }
+}
+
+// Like `rate_calculator`, for counters that wrap around to zero
+// when they reach `range` instead of growing indefinitely
+fn wrapping_rate_calculator<F>(value: F, range: f64) -> RateCalculator<F>
+where
+    F: FnMut() -> Option<f64> + Send + 'static,
+{
+    RateCalculator {
+        range: Some(range),
+        ..rate_calculator(value)
+    }
+}
+
+// Turns a monotonically increasing counter into its rate of change per second
+struct RateCalculator<F> {
+    value: F,
+    range: Option<f64>,
+    last_time: Instant,
+    last_input: Option<f64>,
+}
+
+impl<F> Source for RateCalculator<F>
+where
+    F: FnMut() -> Option<f64> + Send,
+{
+    fn value(&mut self) -> Option<f64> {
+        match (self.value)() {
+            Some(input) => {
+                let now = Instant::now();
+                let dt = ((now - self.last_time).as_millis() as f64) / 1000.0;
+                let range = self.range;
+                let value = self.last_input.map(|last_input| {
+                    if input > last_input {
+                        (input - last_input) / dt
+                    } else {
+                        match range {
+                            Some(range) if input < last_input => (input + range - last_input) / dt,
+                            _ => 0.0,
+                        }
+                    }
+                });
+                self.last_input = Some(input);
+                self.last_time = now;
+                value
+            }
+            None => {
+                self.last_input = None;
+                None
+            }
+        }
     }
 
-    subfeatures
+    fn reset(&mut self) {
+        self.last_input = None;
+    }
+}
+
+// Limits a percentage computed from the rate of change of a counter to 100%,
+// which can be exceeded slightly because the kernel updates such counters
+// while the measured activity is still in progress
+struct Percentage<S>(S);
+
+impl<S: Source> Source for Percentage<S> {
+    fn value(&mut self) -> Option<f64> {
+        self.0.value().map(|value| value.min(100.0))
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ops::Deref;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    // A temporary directory standing in for a sysfs directory,
+    // which is deleted when dropped, even if the test fails
+    pub struct Fixture(PathBuf);
+
+    impl Deref for Fixture {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    // Creates a fixture with the given subdirectories, containing
+    // the given files (with paths relative to the subdirectory) and contents
+    pub fn fixture(name: &str, directories: &[(&str, Vec<(&str, &str)>)]) -> Fixture {
+        let fixture = Fixture(env::temp_dir().join(format!("hegemon-test-{}-{}", name, std::process::id())));
+
+        for (directory, files) in directories {
+            fs::create_dir_all(fixture.join(directory)).unwrap();
+            for (name, contents) in files {
+                let path = fixture.join(directory).join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+        }
+
+        fixture
+    }
+
+    #[test]
+    fn test_group_core_streams() {
+        let streams = [
+            "CPU",
+            "Core1",
+            "Core2",
+            "Core10",
+            "Mem",
+            "Core2Temp",
+            "Core10Temp",
+            "Core1Freq",
+        ]
+        .iter()
+        .map(|name| Stream::new(*name, "", || None, None, None, "", None, 0, false))
+        .collect();
+
+        assert_eq!(
+            group_core_streams(streams).iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec![
+                "CPU",
+                "Core1",
+                "Core1Freq",
+                "Core2",
+                "Core2Temp",
+                "Core10",
+                "Core10Temp",
+                "Mem"
+            ],
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_sensor_streams() {
+        let streams = sensor_streams(
+            SENSORS_FEATURE_IN,
+            SENSORS_SUBFEATURE_IN_INPUT,
+            "Voltage",
+            "V",
+            "Voltage",
+        );
+        assert!(!streams.is_empty());
+        assert!(streams.iter().all(|s| s.name().ends_with("Voltage") && s.unit() == "V"));
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let mut names = HashSet::new();
+        assert_eq!(unique_name("in0", "Voltage", &mut names), "in0Voltage");
+        assert_eq!(unique_name("in1", "Voltage", &mut names), "in1Voltage");
+        assert_eq!(unique_name("in0", "Voltage", &mut names), "in0_2Voltage");
+        assert_eq!(unique_name("in0", "Voltage", &mut names), "in0_3Voltage");
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let stat = "cpu  1 2 3 4\nctxt 12345\nprocs_running 3\nprocs_blocked 1\n";
+        assert_eq!(parse_value(stat, "procs_running"), Some(3.0));
+        assert_eq!(parse_value(stat, "procs_blocked"), Some(1.0));
+        assert_eq!(parse_value(stat, "procs"), None);
+
+        let vmstat = "pgpgin 1882596\npswpin 12\npswpout 340\npgfault 98765432\npgmajfault 4321\n";
+        assert_eq!(parse_value(vmstat, "pswpin"), Some(12.0));
+        assert_eq!(parse_value(vmstat, "pgmajfault"), Some(4321.0));
+        assert_eq!(parse_value(vmstat, "oom_kill"), None);
+    }
+
+    #[test]
+    fn test_rate_calculator() {
+        let mut counter = 0.0;
+        let mut rate = rate_calculator(move || {
+            counter += 1000.0;
+            Some(counter)
+        });
+
+        assert_eq!(rate.value(), None);
+        assert!(rate.value().unwrap() > 0.0);
+
+        rate.reset();
+        assert_eq!(rate.value(), None);
+        assert!(rate.value().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_wrapping_rate_calculator() {
+        let mut values = vec![Some(900.0), Some(100.0), Some(100.0)];
+        let mut rate = wrapping_rate_calculator(move || values.remove(0), 1000.0);
+
+        assert_eq!(rate.value(), None);
+        thread::sleep(Duration::from_millis(100));
+        let value = rate.value().unwrap();
+        assert!(value > 500.0 && value <= 2000.0);
+        assert_eq!(rate.value(), Some(0.0));
+    }
 }
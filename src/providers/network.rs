@@ -15,13 +15,24 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::Instant;
+use std::fs;
+use std::path::Path;
 
 use systemstat::{Platform, System};
 
-use crate::stream::{Stream, StreamProvider};
+use crate::providers::rate_calculator;
+use crate::stream::{Source, Stream, StreamProvider};
 
-pub struct BandwidthStreamProvider {}
+const NET_PATH: &str = "/sys/class/net";
+
+// Interfaces that don't correspond to network hardware, but are created for
+// each container by Docker, and would otherwise clutter the list of streams
+const VIRTUAL_INTERFACE_PREFIXES: &[&str] = &["docker", "veth"];
+const LOOPBACK_INTERFACE: &str = "lo";
+
+pub struct BandwidthStreamProvider {
+    pub exclude_virtual_interfaces: bool,
+}
 
 impl StreamProvider for BandwidthStreamProvider {
     fn streams(&self) -> Vec<Box<dyn Stream>> {
@@ -29,41 +40,123 @@ impl StreamProvider for BandwidthStreamProvider {
         let platform = System::new();
 
         if let Ok(networks) = platform.networks() {
-            for network in networks.values() {
-                let name = network.name.clone();
-                streams.push(Stream::new(
-                    format!("{}Rx", name),
-                    format!("Ingress bandwidth on {} during the past interval", network.name),
-                    rate_calculator(move || {
-                        System::new()
-                            .network_stats(&name)
-                            .ok()
-                            .map(|stats| stats.rx_bytes.as_u64() as f64)
-                    }),
-                    Some(0.0),
-                    None,
-                    "B",
-                    None,
-                    1,
-                    false,
-                ));
-                let name = network.name.clone();
-                streams.push(Stream::new(
-                    format!("{}Tx", name),
-                    format!("Egress bandwidth on {} during the past interval", network.name),
-                    rate_calculator(move || {
-                        System::new()
-                            .network_stats(&name)
-                            .ok()
-                            .map(|stats| stats.tx_bytes.as_u64() as f64)
-                    }),
-                    Some(0.0),
-                    None,
-                    "B",
-                    None,
-                    1,
-                    false,
-                ));
+            let mut interfaces = networks
+                .values()
+                .map(|network| network.name.clone())
+                .filter(|name| !(self.exclude_virtual_interfaces && is_virtual(name)))
+                .collect::<Vec<_>>();
+
+            interfaces.sort();
+
+            // Only interfaces backed by a device carry traffic that enters or leaves the machine,
+            // whereas traffic on virtual interfaces (e.g. bridges) would be counted twice
+            let physical_interfaces = interfaces
+                .iter()
+                .filter(|name| Path::new(NET_PATH).join(name).join("device").exists())
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !physical_interfaces.is_empty() {
+                for &(suffix, counter_name, description) in &[
+                    ("Rx", "rx_bytes", "Total ingress bandwidth"),
+                    ("Tx", "tx_bytes", "Total egress bandwidth"),
+                ] {
+                    streams.push(
+                        Stream::new(
+                            format!("Net{}", suffix),
+                            format!(
+                                "{} on all physical interfaces ({}) during the past interval",
+                                description,
+                                physical_interfaces.join(", "),
+                            ),
+                            TotalRate(
+                                physical_interfaces
+                                    .iter()
+                                    .map(|interface| counter(interface, counter_name))
+                                    .collect(),
+                            ),
+                            Some(0.0),
+                            None,
+                            "B",
+                            None,
+                            1,
+                            false,
+                        )
+                        .as_rate(),
+                    );
+                }
+            }
+
+            for interface in interfaces {
+                let name = interface.clone();
+                streams.push(
+                    Stream::new(
+                        format!("{}Rx", name),
+                        format!("Ingress bandwidth on {} during the past interval", interface),
+                        rate_calculator(move || {
+                            System::new()
+                                .network_stats(&name)
+                                .ok()
+                                .map(|stats| stats.rx_bytes.as_u64() as f64)
+                        }),
+                        Some(0.0),
+                        None,
+                        "B",
+                        None,
+                        1,
+                        false,
+                    )
+                    .as_rate(),
+                );
+                let name = interface.clone();
+                streams.push(
+                    Stream::new(
+                        format!("{}Tx", name),
+                        format!("Egress bandwidth on {} during the past interval", interface),
+                        rate_calculator(move || {
+                            System::new()
+                                .network_stats(&name)
+                                .ok()
+                                .map(|stats| stats.tx_bytes.as_u64() as f64)
+                        }),
+                        Some(0.0),
+                        None,
+                        "B",
+                        None,
+                        1,
+                        false,
+                    )
+                    .as_rate(),
+                );
+
+                for &(suffix, counter_name, description) in &[
+                    ("RxPackets", "rx_packets", "Packets received"),
+                    ("TxPackets", "tx_packets", "Packets sent"),
+                    ("RxErrors", "rx_errors", "Receive errors"),
+                    ("TxErrors", "tx_errors", "Transmit errors"),
+                    ("RxDrops", "rx_dropped", "Received packets dropped"),
+                    ("TxDrops", "tx_dropped", "Packets dropped before sending"),
+                ] {
+                    if statistic(&interface, counter_name).is_none() {
+                        continue;
+                    }
+
+                    streams.push(
+                        Stream::new(
+                            format!("{}{}", interface, suffix),
+                            format!("{} per second on {} during the past interval", description, interface),
+                            counter(&interface, counter_name),
+                            Some(0.0),
+                            None,
+                            "",
+                            None,
+                            1,
+                            false,
+                        )
+                        .as_rate()
+                        .inactive_by_default(),
+                    );
+                }
             }
         }
 
@@ -71,41 +164,84 @@ impl StreamProvider for BandwidthStreamProvider {
     }
 }
 
-fn rate_calculator<F>(mut value: F) -> impl FnMut() -> Option<f64> + 'static
-where
-    F: FnMut() -> Option<f64> + 'static,
-{
-    let mut last_time = Instant::now();
-    let mut last_input = None;
-    move || match value() {
-        Some(input) => {
-            let now = Instant::now();
-            let dt = ((now - last_time).as_millis() as f64) / 1000.0;
-            let value = last_input.map(|last_input| {
-                if input > last_input {
-                    (input - last_input) / dt
-                } else {
-                    0.0
-                }
-            });
-            last_input = Some(input);
-            last_time = now;
-            value
-        }
-        None => {
-            last_input = None;
-            None
+fn is_virtual(interface: &str) -> bool {
+    interface == LOOPBACK_INTERFACE || VIRTUAL_INTERFACE_PREFIXES.iter().any(|p| interface.starts_with(p))
+}
+
+// Returns a source for the rate of change per second of the given statistic
+fn counter(interface: &str, name: &'static str) -> impl Source {
+    let interface = String::from(interface);
+    rate_calculator(move || statistic(&interface, name))
+}
+
+// Sums the rates of the given sources, leaving out those without a value.
+// Each interface's counter has its own rate, so that an interface disappearing
+// or its counters being reset doesn't affect the rates of the other interfaces.
+struct TotalRate<S>(Vec<S>);
+
+impl<S: Source> Source for TotalRate<S> {
+    fn value(&mut self) -> Option<f64> {
+        self.0
+            .iter_mut()
+            .filter_map(|source| source.value())
+            .fold(None, |total, value| Some(total.unwrap_or(0.0) + value))
+    }
+
+    fn reset(&mut self) {
+        for source in &mut self.0 {
+            source.reset();
         }
     }
 }
 
+fn statistic(interface: &str, name: &str) -> Option<f64> {
+    let path = Path::new(NET_PATH).join(interface).join("statistics").join(name);
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_bandwidth_stream_provider() {
-        let streams = BandwidthStreamProvider {}.streams();
+        let streams = BandwidthStreamProvider {
+            exclude_virtual_interfaces: false,
+        }
+        .streams();
         assert!(!streams.is_empty());
+        assert!(streams.iter().any(|s| s.name() == "loRx"));
+
+        let streams = BandwidthStreamProvider {
+            exclude_virtual_interfaces: true,
+        }
+        .streams();
+        assert!(!streams.iter().any(|s| s.name().starts_with("lo")));
+    }
+
+    #[test]
+    fn test_total_rate() {
+        let mut values = vec![None, Some(2.0), None];
+        let mut total = TotalRate(vec![
+            Box::new(|| Some(1.0)) as Box<dyn FnMut() -> Option<f64> + Send>,
+            Box::new(move || values.remove(0)),
+        ]);
+
+        // A missing interface doesn't invalidate the total of the others
+        assert_eq!(total.value(), Some(1.0));
+        assert_eq!(total.value(), Some(3.0));
+
+        let mut total = TotalRate(vec![|| None, || None]);
+        assert_eq!(total.value(), None);
+    }
+
+    #[test]
+    fn test_is_virtual() {
+        assert!(is_virtual("lo"));
+        assert!(is_virtual("docker0"));
+        assert!(is_virtual("veth1a2b3c4"));
+        assert!(!is_virtual("eth0"));
+        assert!(!is_virtual("wlp3s0"));
+        assert!(!is_virtual("lowpan0"));
     }
 }
@@ -0,0 +1,99 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use sensors::{
+    FeatureType::{SENSORS_FEATURE_ENERGY, SENSORS_FEATURE_POWER},
+    SubfeatureType::{
+        SENSORS_SUBFEATURE_ENERGY_INPUT, SENSORS_SUBFEATURE_POWER_AVERAGE, SENSORS_SUBFEATURE_POWER_INPUT,
+    },
+};
+
+use crate::providers::{rate_calculator, subfeatures, unique_name};
+use crate::stream::{Stream, StreamProvider};
+
+pub struct PowerStreamProvider {}
+
+impl StreamProvider for PowerStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        let mut names = HashSet::new();
+
+        let mut power_subfeatures = subfeatures(SENSORS_FEATURE_POWER, SENSORS_SUBFEATURE_POWER_INPUT);
+
+        // Some drivers (e.g. amdgpu) only report power averaged over a period of time
+        for (subfeature, feature_label, chip_name) in
+            subfeatures(SENSORS_FEATURE_POWER, SENSORS_SUBFEATURE_POWER_AVERAGE)
+        {
+            if !power_subfeatures
+                .iter()
+                .any(|(s, _, _)| s.chip_name == subfeature.chip_name && s.feature_name == subfeature.feature_name)
+            {
+                power_subfeatures.push((subfeature, feature_label, chip_name));
+            }
+        }
+
+        for (subfeature, feature_label, chip_name) in power_subfeatures {
+            streams.push(Stream::new(
+                unique_name(&feature_label.replace(" ", ""), "Power", &mut names),
+                format!("Power (feature {} on chip {})", feature_label, chip_name),
+                move || subfeature.value(),
+                Some(0.0),
+                None,
+                "W",
+                Some(3),
+                1,
+                false,
+            ));
+        }
+
+        for (subfeature, feature_label, chip_name) in
+            subfeatures(SENSORS_FEATURE_ENERGY, SENSORS_SUBFEATURE_ENERGY_INPUT)
+        {
+            streams.push(Stream::new(
+                unique_name(&feature_label.replace(" ", ""), "Power", &mut names),
+                format!(
+                    "Power derived from energy consumption during the past interval (feature {} on chip {})",
+                    feature_label, chip_name,
+                ),
+                // Joules per second are watts
+                rate_calculator(move || subfeature.value()),
+                Some(0.0),
+                None,
+                "W",
+                Some(3),
+                1,
+                false,
+            ));
+        }
+
+        streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn test_power_stream_provider() {
+        let streams = PowerStreamProvider {}.streams();
+        assert!(!streams.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use crate::providers::{rate_calculator, Percentage};
+use crate::stream::{Stream, StreamProvider};
+
+const PRESSURE_PATH: &str = "/proc/pressure";
+
+pub struct PressureStreamProvider {}
+
+impl StreamProvider for PressureStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        // "Full" pressure is undefined for CPUs at the system level
+        for &(resource, name, description, full) in &[
+            ("cpu", "CPU", "CPU time", false),
+            ("memory", "Mem", "memory", true),
+            ("io", "IO", "I/O", true),
+        ] {
+            let path = format!("{}/{}", PRESSURE_PATH, resource);
+
+            for &(kind, suffix, tasks) in &[
+                ("some", "", "at least one task was"),
+                ("full", "Full", "all non-idle tasks were"),
+            ] {
+                if (kind == "full" && !full) || total_stall_time(&path, kind).is_none() {
+                    continue;
+                }
+
+                let path = path.clone();
+
+                streams.push(Stream::new(
+                    format!("{}Pressure{}", name, suffix),
+                    format!(
+                        "Percentage of time {} stalled waiting for {} during the past interval",
+                        tasks, description,
+                    ),
+                    // Microseconds stalled per second, divided by 1000000 microseconds
+                    // and multiplied by 100 percent
+                    Percentage(rate_calculator(move || {
+                        total_stall_time(&path, kind).map(|total| total / 10_000.0)
+                    })),
+                    Some(0.0),
+                    Some(100.0),
+                    "%",
+                    Some(3),
+                    1,
+                    false,
+                ));
+            }
+        }
+
+        streams
+    }
+}
+
+// Returns the total time in microseconds that tasks were stalled,
+// as reported by the given pressure file for the given kind ("some" or "full")
+fn total_stall_time(path: &str, kind: &str) -> Option<f64> {
+    parse_total_stall_time(&fs::read_to_string(path).ok()?, kind)
+}
+
+fn parse_total_stall_time(pressure: &str, kind: &str) -> Option<f64> {
+    let line = pressure.lines().find(|l| l.split_whitespace().next() == Some(kind))?;
+
+    line.split_whitespace()
+        .find(|field| field.starts_with("total="))
+        .and_then(|field| field.trim_start_matches("total=").parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_total_stall_time() {
+        let pressure = "some avg10=2.54 avg60=3.23 avg300=2.82 total=41162954\n\
+                        full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+
+        assert_eq!(parse_total_stall_time(pressure, "some"), Some(41162954.0));
+        assert_eq!(parse_total_stall_time(pressure, "full"), Some(0.0));
+        assert_eq!(parse_total_stall_time("some avg10=2.54\n", "some"), None);
+    }
+}
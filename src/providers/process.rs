@@ -0,0 +1,197 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use crate::processes::{clock_ticks_per_second, page_size, rss, stat, Stat};
+use crate::providers::rate_calculator;
+use crate::stream::{Stream, StreamProvider};
+
+const PROC_PATH: &str = "/proc";
+const READ_BYTES: &str = "read_bytes";
+const WRITE_BYTES: &str = "write_bytes";
+
+// Streams for a single process, which is identified by its PID and start time
+// so that the streams don't pick up another process that reuses the PID
+pub struct ProcessStreamProvider {
+    pub pid: u32,
+}
+
+impl StreamProvider for ProcessStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        let pid = self.pid;
+
+        let (name, start_time) = match stat(pid) {
+            Some(stat) => (stat.name, stat.start_time),
+            None => return streams,
+        };
+
+        let prefix = format!("{}[{}]", name, pid);
+        let ticks_per_second = clock_ticks_per_second();
+        let page_size = page_size();
+
+        streams.push(
+            Stream::new(
+                format!("{}CPU", prefix),
+                format!(
+                    "CPU usage of process {} ({}) during the past interval, relative to one core",
+                    pid, name,
+                ),
+                rate_calculator(move || {
+                    process_stat(pid, start_time).map(|stat| stat.cpu_time as f64 / ticks_per_second * 100.0)
+                }),
+                Some(0.0),
+                None,
+                "%",
+                Some(4),
+                1,
+                false,
+            )
+            .with_soft_max(100.0),
+        );
+
+        streams.push(Stream::new(
+            format!("{}RSS", prefix),
+            format!("Physical memory (RAM) used by process {} ({})", pid, name),
+            move || {
+                process_stat(pid, start_time)?;
+                rss(pid, page_size).map(|rss| rss as f64)
+            },
+            Some(0.0),
+            None,
+            "B",
+            None,
+            1,
+            false,
+        ));
+
+        // I/O statistics are only accessible for the user's own processes
+        if io(pid, READ_BYTES).is_some() {
+            for &(suffix, key, description) in
+                &[("Read", READ_BYTES, "read from"), ("Write", WRITE_BYTES, "written to")]
+            {
+                streams.push(
+                    Stream::new(
+                        format!("{}{}", prefix, suffix),
+                        format!(
+                            "Bytes {} storage by process {} ({}) during the past interval",
+                            description, pid, name,
+                        ),
+                        rate_calculator(move || {
+                            process_stat(pid, start_time)?;
+                            io(pid, key)
+                        }),
+                        Some(0.0),
+                        None,
+                        "B",
+                        None,
+                        1,
+                        false,
+                    )
+                    .as_rate(),
+                );
+            }
+        }
+
+        streams.push(Stream::new(
+            format!("{}Threads", prefix),
+            format!("Number of threads of process {} ({})", pid, name),
+            move || process_stat(pid, start_time).map(|stat| stat.threads as f64),
+            Some(0.0),
+            None,
+            "",
+            Some(5),
+            0,
+            false,
+        ));
+
+        if file_descriptors(pid).is_some() {
+            streams.push(Stream::new(
+                format!("{}FDs", prefix),
+                format!("Number of files opened by process {} ({})", pid, name),
+                move || {
+                    process_stat(pid, start_time)?;
+                    file_descriptors(pid)
+                },
+                Some(0.0),
+                None,
+                "",
+                Some(6),
+                0,
+                false,
+            ));
+        }
+
+        streams
+    }
+}
+
+// Returns `None` if the process has exited
+fn process_stat(pid: u32, start_time: u64) -> Option<Stat> {
+    stat(pid).filter(|stat| stat.start_time == start_time)
+}
+
+fn io(pid: u32, key: &str) -> Option<f64> {
+    parse_io(&fs::read_to_string(format!("{}/{}/io", PROC_PATH, pid)).ok()?, key)
+}
+
+fn file_descriptors(pid: u32) -> Option<f64> {
+    fs::read_dir(format!("{}/{}/fd", PROC_PATH, pid))
+        .ok()
+        .map(|entries| entries.count() as f64)
+}
+
+// Extracts the value with the given key from the contents of `/proc/<pid>/io`
+fn parse_io(io: &str, key: &str) -> Option<f64> {
+    io.lines().find_map(|line| {
+        let mut parts = line.splitn(2, ':');
+        if parts.next() == Some(key) {
+            parts.next().and_then(|value| value.trim().parse().ok())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_io() {
+        let io = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+                  read_bytes: 73728\nwrite_bytes: 4096\ncancelled_write_bytes: 0\n";
+        assert_eq!(parse_io(io, READ_BYTES), Some(73728.0));
+        assert_eq!(parse_io(io, WRITE_BYTES), Some(4096.0));
+        assert_eq!(parse_io(io, "bytes"), None);
+    }
+
+    #[test]
+    fn test_process_stream_provider() {
+        let mut streams = ProcessStreamProvider { pid: process::id() }.streams();
+        let names = streams.iter().map(|s| s.name()).collect::<Vec<_>>();
+        assert!(names[0].ends_with(&format!("[{}]CPU", process::id())));
+
+        let threads = streams.iter_mut().find(|s| s.name().ends_with("Threads")).unwrap();
+        assert!(threads.value().unwrap() >= 1.0);
+
+        assert!(ProcessStreamProvider { pid: 0 }.streams().is_empty());
+    }
+}
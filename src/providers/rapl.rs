@@ -0,0 +1,166 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::providers::{read_string, unique_name, wrapping_rate_calculator};
+use crate::stream::{Stream, StreamProvider};
+
+const POWERCAP_PATH: &str = "/sys/class/powercap";
+
+// Energy counters are reported in microjoules
+const MICROJOULES_PER_JOULE: f64 = 1_000_000.0;
+
+// Running Average Power Limit (RAPL) energy counters of CPU packages
+// and their subdomains (e.g. cores and DRAM)
+pub struct RAPLStreamProvider {}
+
+impl StreamProvider for RAPLStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        rapl_streams(Path::new(POWERCAP_PATH))
+    }
+}
+
+fn rapl_streams(powercap_path: &Path) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
+
+    let mut names = HashSet::new();
+
+    // Zones are named after the control type and their position in the hierarchy,
+    // e.g. "intel-rapl:0" for a package and "intel-rapl:0:2" for its DRAM
+    let mut zones = match fs::read_dir(powercap_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| name.starts_with("intel-rapl") && name.contains(':'))
+            .collect::<Vec<_>>(),
+        Err(_) => return streams,
+    };
+
+    zones.sort();
+
+    for zone in zones {
+        let zone_path = powercap_path.join(&zone);
+
+        // Energy counters are only readable by root on most systems
+        let energy_path = zone_path.join("energy_uj");
+        let (domain, range) = match (
+            read_string(&zone_path.join("name")),
+            joules(&energy_path),
+            joules(&zone_path.join("max_energy_range_uj")),
+        ) {
+            (Some(domain), Some(_), Some(range)) => (domain, range),
+            _ => continue,
+        };
+
+        // Subdomains are named relative to their package (e.g. "dram")
+        let name = match zone.rfind(':').map(|i| &zone[..i]) {
+            Some(parent) if parent.contains(':') => match read_string(&powercap_path.join(parent).join("name")) {
+                Some(parent_domain) => format!("{}{}", title_case(&parent_domain), title_case(&domain)),
+                None => title_case(&domain),
+            },
+            _ => title_case(&domain),
+        };
+
+        streams.push(Stream::new(
+            unique_name(&name, "Power", &mut names),
+            format!(
+                "Power consumption of RAPL domain {} ({}) during the past interval",
+                domain, zone,
+            ),
+            // Joules per second are watts
+            wrapping_rate_calculator(move || joules(&energy_path), range),
+            Some(0.0),
+            None,
+            "W",
+            Some(3),
+            1,
+            false,
+        ));
+    }
+
+    streams
+}
+
+// Converts a domain name such as "package-0" to "Package0"
+fn title_case(name: &str) -> String {
+    name.split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+// Reads an energy in microjoules and returns it in joules
+fn joules(path: &Path) -> Option<f64> {
+    read_string(path)?
+        .parse::<f64>()
+        .ok()
+        .map(|microjoules| microjoules / MICROJOULES_PER_JOULE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tests::fixture;
+
+    #[test]
+    fn test_rapl_streams() {
+        let powercap_path = fixture(
+            "powercap",
+            &[
+                (
+                    "intel-rapl:0",
+                    vec![
+                        ("name", "package-0\n"),
+                        ("energy_uj", "262143328850\n"),
+                        ("max_energy_range_uj", "262143328850\n"),
+                    ],
+                ),
+                (
+                    "intel-rapl:0:0",
+                    vec![
+                        ("name", "dram\n"),
+                        ("energy_uj", "1000000\n"),
+                        ("max_energy_range_uj", "262143328850\n"),
+                    ],
+                ),
+                (
+                    "intel-rapl:1",
+                    vec![("name", "psys\n"), ("max_energy_range_uj", "262143328850\n")],
+                ),
+                ("intel-rapl", vec![]),
+            ],
+        );
+
+        let streams = rapl_streams(&powercap_path);
+
+        assert_eq!(
+            streams.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec!["Package0Power", "Package0DramPower"],
+        );
+        assert_eq!(
+            streams[1].description(),
+            "Power consumption of RAPL domain dram (intel-rapl:0:0) during the past interval",
+        );
+    }
+}
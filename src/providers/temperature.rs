@@ -14,61 +14,187 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use sensors::{FeatureType::SENSORS_FEATURE_TEMP, SubfeatureType::SENSORS_SUBFEATURE_TEMP_INPUT};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
 
-use crate::providers::subfeatures;
+use sensors::{
+    FeatureType::SENSORS_FEATURE_TEMP,
+    SubfeatureType::{SENSORS_SUBFEATURE_TEMP_CRIT, SENSORS_SUBFEATURE_TEMP_INPUT, SENSORS_SUBFEATURE_TEMP_MAX},
+};
+
+use crate::providers::{read_string, subfeatures, unique_name};
 use crate::stream::{Stream, StreamProvider};
 
+const THERMAL_PATH: &str = "/sys/class/thermal";
+
 pub struct TemperatureStreamProvider {}
 
 impl StreamProvider for TemperatureStreamProvider {
     fn streams(&self) -> Vec<Box<dyn Stream>> {
         let mut streams = Vec::new();
 
+        // Multiple sensors can have the same label (e.g. two NVMe drives)
+        let mut names = HashSet::new();
+
         let mut core_index = 0;
         let mut package_index = 0;
 
         for (subfeature, feature_label, chip_name) in subfeatures(SENSORS_FEATURE_TEMP, SENSORS_SUBFEATURE_TEMP_INPUT) {
-            let name_description = if feature_label.to_lowercase() == "cpu" {
-                Some((String::from("CPU"), String::from("Temperature of CPU")))
+            let (name, description) = if feature_label.to_lowercase() == "cpu" {
+                (String::from("CPU"), String::from("Temperature of CPU"))
             } else if feature_label.to_lowercase().contains("core") {
                 core_index += 1;
-                Some((
+                (
                     format!("Core{}", core_index),
                     format!("Temperature of CPU core {}", core_index),
-                ))
+                )
             } else if feature_label.to_lowercase().contains("package id") {
                 package_index += 1;
-                Some((
+                (
                     format!("Package {}", package_index),
                     format!("Temperature of CPU package {}", package_index),
-                ))
+                )
             } else {
-                None
+                // Chip names consist of a driver name and an address (e.g. "nvme-pci-0100")
+                let driver = chip_name.split('-').next().unwrap_or(&chip_name);
+                (
+                    format!("{}{}", driver, feature_label.replace(" ", "")),
+                    format!("Temperature of {} sensor", driver),
+                )
             };
 
-            if let Some((name, description)) = name_description {
-                streams.push(Stream::new(
-                    format!("{}Temp", name),
-                    format!("{} (feature {} on chip {})", description, feature_label, chip_name),
-                    move || subfeature.get_value().ok(),
-                    None,
-                    None,
-                    "°C",
-                    Some(3),
-                    1,
-                    true,
-                ));
-            }
+            let high = threshold(subfeature.sibling_value(SENSORS_SUBFEATURE_TEMP_MAX));
+            let critical = threshold(subfeature.sibling_value(SENSORS_SUBFEATURE_TEMP_CRIT));
+
+            streams.push(temperature_stream(
+                unique_name(&name, "Temp", &mut names),
+                format!(
+                    "{} (feature {} on chip {}){}",
+                    description,
+                    feature_label,
+                    chip_name,
+                    thresholds_description(high, critical),
+                ),
+                move || subfeature.value(),
+                high,
+                critical,
+            ));
+        }
+
+        if streams.is_empty() {
+            streams = thermal_zone_streams(Path::new(THERMAL_PATH));
         }
 
         streams
     }
 }
 
+// Thermal zones are provided by the kernel even without libsensors
+// support for the hardware, e.g. in laptops and single-board computers
+fn thermal_zone_streams(thermal_path: &Path) -> Vec<Box<dyn Stream>> {
+    let mut streams = Vec::new();
+
+    let mut names = HashSet::new();
+
+    let mut zones = match fs::read_dir(thermal_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| name.starts_with("thermal_zone"))
+            .filter_map(|name| name.trim_start_matches("thermal_zone").parse::<usize>().ok())
+            .collect::<Vec<_>>(),
+        Err(_) => return streams,
+    };
+
+    zones.sort();
+
+    for zone in zones {
+        let zone_path = thermal_path.join(format!("thermal_zone{}", zone));
+
+        let temperature_path = zone_path.join("temp");
+        if temperature(&temperature_path).is_none() {
+            continue;
+        }
+
+        let zone_type = read_string(&zone_path.join("type")).unwrap_or_else(|| format!("zone{}", zone));
+
+        let high = threshold(trip_point(&zone_path, "hot"));
+        let critical = threshold(trip_point(&zone_path, "critical"));
+
+        streams.push(temperature_stream(
+            unique_name(zone_type.trim_end_matches("_temp"), "Temp", &mut names),
+            format!(
+                "Temperature of thermal zone {} ({}){}",
+                zone,
+                zone_type,
+                thresholds_description(high, critical),
+            ),
+            move || temperature(&temperature_path),
+            high,
+            critical,
+        ));
+    }
+
+    streams
+}
+
+fn temperature_stream(
+    name: String,
+    description: String,
+    value: impl FnMut() -> Option<f64> + Send + 'static,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Box<dyn Stream> {
+    let stream = Stream::new(name, description, value, None, None, "°C", Some(3), 1, true);
+
+    // Thresholds are only hints, as the temperature can exceed them
+    match critical.or(high) {
+        Some(soft_max) => stream.with_soft_max(soft_max),
+        None => stream,
+    }
+}
+
+// Sensors without thresholds often report nonsensical values such as 0 or 65261.8
+fn threshold(temperature: Option<f64>) -> Option<f64> {
+    temperature.filter(|&t| t > 0.0 && t < 1000.0)
+}
+
+fn thresholds_description(high: Option<f64>, critical: Option<f64>) -> String {
+    match (high, critical) {
+        (Some(high), Some(critical)) => format!(", high {}°C, critical {}°C", high, critical),
+        (Some(high), None) => format!(", high {}°C", high),
+        (None, Some(critical)) => format!(", critical {}°C", critical),
+        (None, None) => String::new(),
+    }
+}
+
+// Returns the temperature of the first trip point of the given type (e.g. "critical")
+fn trip_point(zone_path: &Path, trip_point_type: &str) -> Option<f64> {
+    (0..)
+        .map(|i| {
+            (
+                zone_path.join(format!("trip_point_{}_type", i)),
+                zone_path.join(format!("trip_point_{}_temp", i)),
+            )
+        })
+        .take_while(|(type_path, _)| type_path.exists())
+        .find(|(type_path, _)| read_string(type_path).as_deref() == Some(trip_point_type))
+        .and_then(|(_, temperature_path)| temperature(&temperature_path))
+}
+
+// Reads a temperature in millidegrees Celsius and returns it in degrees Celsius
+fn temperature(path: &Path) -> Option<f64> {
+    read_string(path)?
+        .parse::<f64>()
+        .ok()
+        .map(|millidegrees| millidegrees / 1000.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::providers::tests::fixture;
 
     #[test]
     #[ignore]
@@ -76,4 +202,41 @@ mod tests {
         let streams = TemperatureStreamProvider {}.streams();
         assert!(!streams.is_empty());
     }
+
+    #[test]
+    fn test_thermal_zone_streams() {
+        let thermal_path = fixture(
+            "thermal",
+            &[
+                (
+                    "thermal_zone0",
+                    vec![
+                        ("type", "acpitz\n"),
+                        ("temp", "47000\n"),
+                        ("trip_point_0_type", "passive\n"),
+                        ("trip_point_0_temp", "90000\n"),
+                        ("trip_point_1_type", "critical\n"),
+                        ("trip_point_1_temp", "105000\n"),
+                    ],
+                ),
+                ("thermal_zone1", vec![("type", "acpitz\n"), ("temp", "38500\n")]),
+                ("thermal_zone2", vec![("type", "x86_pkg_temp\n")]),
+            ],
+        );
+
+        let mut streams = thermal_zone_streams(&thermal_path);
+        let value = streams[1].value();
+
+        assert_eq!(
+            streams.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec!["acpitzTemp", "acpitz_2Temp"],
+        );
+        assert_eq!(
+            streams[0].description(),
+            "Temperature of thermal zone 0 (acpitz), critical 105°C",
+        );
+        assert_eq!(streams[0].soft_max(), Some(105.0));
+        assert_eq!(streams[1].soft_max(), None);
+        assert_eq!(value, Some(38.5));
+    }
 }
@@ -0,0 +1,83 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use crate::providers::{parse_value, rate_calculator};
+use crate::stream::{Stream, StreamProvider};
+
+const VMSTAT_PATH: &str = "/proc/vmstat";
+
+pub struct VMStatStreamProvider {}
+
+impl StreamProvider for VMStatStreamProvider {
+    fn streams(&self) -> Vec<Box<dyn Stream>> {
+        let mut streams = Vec::new();
+
+        for &(name, key, description) in &[
+            (
+                "SwapIn",
+                "pswpin",
+                "Pages swapped in from disk per second during the past interval",
+            ),
+            (
+                "SwapOut",
+                "pswpout",
+                "Pages swapped out to disk per second during the past interval",
+            ),
+            (
+                "MajorFaults",
+                "pgmajfault",
+                "Page faults requiring disk I/O per second during the past interval",
+            ),
+            (
+                "PageFaults",
+                "pgfault",
+                "Page faults per second during the past interval",
+            ),
+            (
+                "OOMKills",
+                "oom_kill",
+                "Processes killed by the out-of-memory killer per second during the past interval",
+            ),
+        ] {
+            if vmstat(key).is_none() {
+                continue;
+            }
+
+            streams.push(
+                Stream::new(
+                    name,
+                    description,
+                    rate_calculator(move || vmstat(key)),
+                    Some(0.0),
+                    None,
+                    "",
+                    None,
+                    1,
+                    false,
+                )
+                .as_rate(),
+            );
+        }
+
+        streams
+    }
+}
+
+fn vmstat(key: &str) -> Option<f64> {
+    parse_value(&fs::read_to_string(VMSTAT_PATH).ok()?, key)
+}
@@ -0,0 +1,363 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Recordings are append-only text files with one record per line:
+//
+// `S <id> <min> <max> <name>\t<description>` declares the stream with the given numeric ID
+// (`min` and `max` are `-` if the stream doesn't define them).
+// IDs are only valid until they are declared again, which allows multiple
+// recording sessions to append to the same file.
+//
+// `T <time> <id>=<value> <id>=<value> ...` contains the values recorded
+// during one update, with the time in milliseconds since the Unix epoch.
+// Streams without a value are omitted.
+//
+// A text format is used rather than a binary one so that recordings can be inspected
+// and processed with standard tools, and so that a recording whose last record
+// was cut off (e.g. because the application was killed) remains readable.
+// Recordings are compact nonetheless: Streams are referred to by short IDs,
+// missing values take up no space, and values are written with the fewest digits
+// that represent them exactly, so a typical value takes up only a few bytes.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use termion::color::Fg;
+
+use crate::stream::{Formatter, Stream};
+use crate::theme::Theme;
+use crate::view::format_quantity;
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    ids: HashMap<String, usize>,
+}
+
+impl Recorder {
+    /// Opens the given file for recording, appending to any existing recording.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+
+        // If the last record was cut off, the records of this session
+        // must not be appended to it, which would make it unreadable
+        if file.metadata()?.len() > 0 {
+            let mut last_byte = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last_byte)?;
+            if last_byte[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+
+        Ok(Recorder {
+            writer: BufWriter::new(file),
+            ids: HashMap::new(),
+        })
+    }
+
+    /// Records the values of the given streams at the given time
+    /// (in milliseconds since the Unix epoch).
+    pub fn record(&mut self, time: u64, values: &[(&dyn Stream, Option<f64>)]) -> io::Result<()> {
+        let mut line = format!("T {}", time);
+
+        for (stream, value) in values {
+            let name = stream.name();
+
+            let id = match self.ids.get(&name) {
+                Some(&id) => id,
+                None => {
+                    let id = self.ids.len();
+                    writeln!(
+                        self.writer,
+                        "S {} {} {} {}\t{}",
+                        id,
+                        format_limit(stream.min()),
+                        format_limit(stream.max()),
+                        name,
+                        stream.description(),
+                    )?;
+                    self.ids.insert(name, id);
+                    id
+                }
+            };
+
+            if let Some(value) = value {
+                line.push_str(&format!(" {}={}", id, value));
+            }
+        }
+
+        writeln!(self.writer, "{}", line)?;
+
+        // Recordings should be complete even if the application is killed
+        self.writer.flush()
+    }
+}
+
+fn format_limit(limit: Option<f64>) -> String {
+    match limit {
+        Some(limit) => limit.to_string(),
+        None => String::from("-"),
+    }
+}
+
+fn parse_limit(string: &str) -> Option<Option<f64>> {
+    if string == "-" {
+        Some(None)
+    } else {
+        string.parse().ok().map(Some)
+    }
+}
+
+pub struct RecordedStream {
+    pub name: String,
+    pub description: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+pub struct Recording {
+    pub streams: Vec<RecordedStream>,
+    /// Recorded values as tuples of time (in milliseconds since the Unix epoch),
+    /// index into `streams`, and value, in chronological order
+    pub samples: Vec<(u64, usize, f64)>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut streams = Vec::new();
+        let mut samples = Vec::new();
+
+        let mut indices = HashMap::new();
+        let mut stream_indices = HashMap::new();
+
+        let string = fs::read_to_string(path)?;
+        let lines = string.lines().collect::<Vec<_>>();
+
+        for (i, &line) in lines.iter().enumerate() {
+            // A record that is missing its line break was cut off while being written
+            if i == lines.len() - 1 && !string.ends_with('\n') {
+                break;
+            }
+
+            let invalid = || Error::new(ErrorKind::InvalidData, format!("Invalid record on line {}", i + 1));
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut type_record = line.splitn(2, ' ');
+            let record_type = type_record.next().unwrap_or("");
+            let record = type_record.next().ok_or_else(invalid)?;
+
+            if record_type == "S" {
+                let (header, description) = match record.find('\t') {
+                    Some(index) => (&record[..index], &record[(index + 1)..]),
+                    None => (record, ""),
+                };
+
+                let parts = header.splitn(4, ' ').collect::<Vec<_>>();
+                if parts.len() < 4 {
+                    return Err(invalid());
+                }
+
+                let id = parts[0].parse::<usize>().map_err(|_| invalid())?;
+                let min = parse_limit(parts[1]).ok_or_else(invalid)?;
+                let max = parse_limit(parts[2]).ok_or_else(invalid)?;
+                let name = parts[3];
+
+                let index = *stream_indices.entry(String::from(name)).or_insert_with(|| {
+                    streams.push(RecordedStream {
+                        name: String::from(name),
+                        description: String::from(description),
+                        min,
+                        max,
+                    });
+                    streams.len() - 1
+                });
+
+                indices.insert(id, index);
+            } else if record_type == "T" {
+                let mut parts = record.split(' ');
+
+                let time = parts.next().and_then(|t| t.parse::<u64>().ok()).ok_or_else(invalid)?;
+
+                for part in parts {
+                    let mut id_value = part.splitn(2, '=');
+                    let id = id_value.next().and_then(|i| i.parse::<usize>().ok());
+                    let value = id_value.next().and_then(|v| v.parse::<f64>().ok());
+
+                    match (id.and_then(|id| indices.get(&id)), value) {
+                        (Some(&index), Some(value)) => samples.push((time, index, value)),
+                        _ => return Err(invalid()),
+                    }
+                }
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        // Sessions appending to the same file might not be in chronological order
+        samples.sort_by_key(|&(time, _, _)| time);
+
+        Ok(Recording { streams, samples })
+    }
+
+    /// Returns streams for displaying the recorded values. Where possible,
+    /// the given live streams (matched by name) are used, so that values are
+    /// formatted appropriately. Note that the returned streams never return values.
+    pub fn streams(&self, live_streams: Vec<Box<dyn Stream>>) -> Vec<Box<dyn Stream>> {
+        let mut live_streams = live_streams
+            .into_iter()
+            .map(|s| (s.name(), s))
+            .collect::<HashMap<_, _>>();
+
+        self.streams
+            .iter()
+            .map(|recorded| match live_streams.remove(&recorded.name) {
+                Some(stream) => stream,
+                None => Box::new(RecordedStreamPlaceholder {
+                    name: recorded.name.clone(),
+                    description: recorded.description.clone(),
+                    min: recorded.min,
+                    max: recorded.max,
+                }),
+            })
+            .collect()
+    }
+}
+
+// Stand-in for recorded streams that don't exist on this system.
+// Values are shown without units, because these aren't recorded.
+struct RecordedStreamPlaceholder {
+    name: String,
+    description: String,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Stream for RecordedStreamPlaceholder {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn value(&mut self) -> Option<f64> {
+        None
+    }
+
+    fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    fn formatter(&self) -> Formatter {
+        Arc::new(|value: f64, theme: &Theme| {
+            format_quantity(
+                value,
+                "",
+                true,
+                1,
+                Fg(theme.stream_number_color),
+                Fg(theme.stream_unit_color),
+            )
+        })
+    }
+
+    fn format_width(&self) -> usize {
+        // Sign, three digits before and one after the decimal point, and unit prefix
+        1 + 3 + 2 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_recording() {
+        let path = env::temp_dir().join(format!("hegemon-test-recording-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let a = Stream::new("A", "First stream", || None, Some(0.0), None, "", None, 0, false);
+        let b = Stream::new("B B", "Second stream", || None, None, Some(1.5), "", None, 0, false);
+
+        {
+            let mut recorder = Recorder::open(&path).unwrap();
+            recorder.record(1_000, &[(&*a, Some(1.0)), (&*b, None)]).unwrap();
+            recorder.record(2_000, &[(&*a, Some(2.0)), (&*b, Some(-0.5))]).unwrap();
+        }
+
+        // Sessions append to the same file with their own IDs
+        {
+            let mut recorder = Recorder::open(&path).unwrap();
+            recorder.record(3_000, &[(&*b, Some(0.5)), (&*a, Some(3.0))]).unwrap();
+        }
+
+        // A session appended to a recording whose last record was cut off
+        // starts on a new line, leaving the earlier records readable
+        let cut_off = |path: &Path| {
+            OpenOptions::new()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .write_all(b"T 4000 1=4")
+                .unwrap();
+        };
+
+        cut_off(&path);
+        {
+            let mut recorder = Recorder::open(&path).unwrap();
+            recorder.record(5_000, &[(&*a, Some(5.0))]).unwrap();
+        }
+
+        // A record that was cut off at the end of the recording is ignored
+        cut_off(&path);
+
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            recording.streams.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["A", "B B"],
+        );
+        assert_eq!(recording.streams[1].description, "Second stream");
+        assert_eq!(recording.streams[0].min, Some(0.0));
+        assert_eq!(recording.streams[1].max, Some(1.5));
+        assert_eq!(
+            recording.samples,
+            vec![
+                (1_000, 0, 1.0),
+                (2_000, 0, 2.0),
+                (2_000, 1, -0.5),
+                (3_000, 1, 0.5),
+                (3_000, 0, 3.0),
+                (4_000, 0, 4.0),
+                (5_000, 0, 5.0),
+            ],
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// Hegemon - A modular system monitor
+// Copyright (C) 2018-2020  Philipp Emanuel Weidmann <pew@worldwidemann.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{self, Receiver, Sender};
+
+use crate::stream::{Formatter, Stream};
+
+/// A data stream that can be sampled by the `Sampler`'s worker threads.
+/// Properties that never change are cached, so they remain available
+/// while a worker is busy computing the stream's value.
+pub struct SharedStream {
+    stream: Arc<Mutex<Box<dyn Stream>>>,
+    busy: Arc<AtomicBool>,
+    name: String,
+    description: String,
+    min: Option<f64>,
+    max: Option<f64>,
+    soft_max: Option<f64>,
+    forecast: bool,
+    unit: String,
+    rate: bool,
+    formatter: Formatter,
+    format_width: usize,
+}
+
+impl SharedStream {
+    pub fn new(stream: Box<dyn Stream>) -> Self {
+        SharedStream {
+            name: stream.name(),
+            description: stream.description(),
+            min: stream.min(),
+            max: stream.max(),
+            soft_max: stream.soft_max(),
+            forecast: stream.forecast(),
+            unit: stream.unit(),
+            rate: stream.rate(),
+            formatter: stream.formatter(),
+            format_width: stream.format_width(),
+            stream: Arc::new(Mutex::new(stream)),
+            busy: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns `true` if a worker is currently computing this stream's value.
+    pub fn busy(&self) -> bool {
+        self.busy.load(Ordering::SeqCst)
+    }
+}
+
+impl Stream for SharedStream {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn value(&mut self) -> Option<f64> {
+        self.stream.lock().unwrap().value()
+    }
+
+    fn reset(&mut self) {
+        // A stream that is stuck computing a value cannot be reset
+        if let Ok(mut stream) = self.stream.try_lock() {
+            stream.reset();
+        }
+    }
+
+    fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    fn soft_max(&self) -> Option<f64> {
+        self.soft_max
+    }
+
+    fn forecast(&self) -> bool {
+        self.forecast
+    }
+
+    fn unit(&self) -> String {
+        self.unit.clone()
+    }
+
+    fn rate(&self) -> bool {
+        self.rate
+    }
+
+    fn formatter(&self) -> Formatter {
+        self.formatter.clone()
+    }
+
+    fn format_width(&self) -> usize {
+        self.format_width
+    }
+}
+
+struct Job {
+    index: usize,
+    round: u64,
+    stream: Arc<Mutex<Box<dyn Stream>>>,
+    busy: Arc<AtomicBool>,
+}
+
+/// A value computed by a worker thread.
+pub struct Sample {
+    index: usize,
+    round: u64,
+    value: Option<f64>,
+}
+
+/// Computes stream values concurrently on a pool of worker threads,
+/// so that slow or unresponsive streams cannot block the user interface.
+/// Values are computed in rounds: Each round is started with `start`,
+/// after which the samples arriving from `samples` are passed to `receive`
+/// until all values have arrived or the deadline has passed, and the values
+/// are then collected with `finish`.
+pub struct Sampler {
+    jobs: Sender<Job>,
+    job_receiver: Receiver<Job>,
+    samples: Receiver<Sample>,
+    sample_sender: Sender<Sample>,
+    idle_workers: Arc<AtomicUsize>,
+    round: u64,
+    // Values received so far in the current round
+    values: Vec<Option<f64>>,
+    // Number of values that haven't arrived yet in the current round
+    pending: usize,
+    // `None` if no round is in progress
+    deadline: Option<Instant>,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        let (jobs, job_receiver) = crossbeam_channel::unbounded();
+        let (sample_sender, samples) = crossbeam_channel::unbounded();
+
+        Sampler {
+            jobs,
+            job_receiver,
+            samples,
+            sample_sender,
+            idle_workers: Arc::new(AtomicUsize::new(0)),
+            round: 0,
+            values: Vec::new(),
+            pending: 0,
+            deadline: None,
+        }
+    }
+
+    /// Starts computing the values of the given streams,
+    /// discarding the values of any round that hasn't been finished.
+    /// The values of streams that are still busy from an earlier round are `None`.
+    pub fn start(&mut self, streams: &[&SharedStream], timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+
+        self.round += 1;
+
+        let jobs = streams
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !s.busy.swap(true, Ordering::SeqCst))
+            .map(|(i, s)| Job {
+                index: i,
+                round: self.round,
+                stream: s.stream.clone(),
+                busy: s.busy.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        // Workers stuck on unresponsive streams are never idle again,
+        // so the pool grows as needed to ensure that no job has to wait
+        for _ in self.idle_workers.load(Ordering::SeqCst)..jobs.len() {
+            self.spawn_worker();
+        }
+
+        self.values = vec![None; streams.len()];
+        self.pending = jobs.len();
+
+        for job in jobs {
+            self.jobs.send(job).unwrap();
+        }
+    }
+
+    /// Returns the channel through which samples arrive from the worker threads.
+    pub fn samples(&self) -> Receiver<Sample> {
+        self.samples.clone()
+    }
+
+    /// Adds the given sample to the values of the current round.
+    /// Returns `true` if all values of the current round have arrived.
+    pub fn receive(&mut self, sample: Sample) -> bool {
+        // Samples from earlier rounds arrived too late and are discarded
+        if self.deadline.is_some() && sample.round == self.round {
+            self.values[sample.index] = sample.value;
+            self.pending -= 1;
+        }
+
+        self.complete()
+    }
+
+    /// Returns `true` if all values of the current round have arrived.
+    pub fn complete(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Returns the time after which the current round should be finished
+    /// without waiting for further values, or `None` if no round is in progress.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Blocks until all values of the current round have arrived
+    /// or the deadline has passed.
+    pub fn wait(&mut self) {
+        while let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            if self.complete() || now >= deadline {
+                break;
+            }
+
+            match self.samples.recv_timeout(deadline - now) {
+                Ok(sample) => {
+                    self.receive(sample);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Finishes the current round, returning the values of the streams
+    /// it was started with, in the same order. The value of any stream
+    /// that hasn't answered yet is `None`.
+    pub fn finish(&mut self) -> Vec<Option<f64>> {
+        self.deadline = None;
+        self.pending = 0;
+        mem::take(&mut self.values)
+    }
+
+    fn spawn_worker(&self) {
+        let jobs = self.job_receiver.clone();
+        let samples = self.sample_sender.clone();
+        let idle_workers = self.idle_workers.clone();
+
+        idle_workers.fetch_add(1, Ordering::SeqCst);
+
+        thread::spawn(move || {
+            for job in jobs.iter() {
+                idle_workers.fetch_sub(1, Ordering::SeqCst);
+
+                let value = job.stream.lock().unwrap().value();
+
+                job.busy.store(false, Ordering::SeqCst);
+                // The worker must be counted as idle before the sample is received
+                idle_workers.fetch_add(1, Ordering::SeqCst);
+
+                let sample = Sample {
+                    index: job.index,
+                    round: job.round,
+                    value,
+                };
+
+                if samples.send(sample).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::Theme;
+
+    fn sample(sampler: &mut Sampler, streams: &[&SharedStream], timeout: Duration) -> Vec<Option<f64>> {
+        sampler.start(streams, timeout);
+        sampler.wait();
+        sampler.finish()
+    }
+
+    #[test]
+    fn test_sampler() {
+        let fast = SharedStream::new(Stream::new("A", "", || Some(1.0), None, None, "", None, 0, false));
+        let slow = SharedStream::new(Stream::new(
+            "B",
+            "",
+            || {
+                thread::sleep(Duration::from_millis(200));
+                Some(2.0)
+            },
+            None,
+            None,
+            "",
+            None,
+            0,
+            false,
+        ));
+
+        let mut sampler = Sampler::new();
+
+        assert_eq!(
+            sample(&mut sampler, &[&fast, &slow], Duration::from_millis(50)),
+            vec![Some(1.0), None],
+        );
+        assert!(slow.busy());
+        // Values can be formatted while the stream is busy
+        assert!(!slow.format(2.0, &Theme::default()).is_empty());
+
+        // The slow stream is still busy and isn't sampled again
+        assert_eq!(
+            sample(&mut sampler, &[&fast, &slow], Duration::from_millis(50)),
+            vec![Some(1.0), None],
+        );
+
+        thread::sleep(Duration::from_millis(200));
+        assert!(!slow.busy());
+
+        assert_eq!(
+            sample(&mut sampler, &[&fast, &slow], Duration::from_millis(500)),
+            vec![Some(1.0), Some(2.0)],
+        );
+    }
+}
@@ -14,17 +14,22 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::sync::Arc;
+
 use termion::color::Fg;
 
 use crate::theme::Theme;
 use crate::view::{format_quantity, printed_width};
 
+/// A function that turns values into human-readable text, using the colors from the given theme.
+pub type Formatter = Arc<dyn Fn(f64, &Theme) -> String + Send + Sync>;
+
 pub trait StreamProvider {
     /// Returns a list of data stream objects.
     fn streams(&self) -> Vec<Box<dyn Stream>>;
 }
 
-pub trait Stream {
+pub trait Stream: Send {
     /// Returns the name of this data stream, to be used both as an identifier
     /// and for labeling the stream in the user interface.
     /// This method **must** return the same value each time it is called,
@@ -39,6 +44,11 @@ pub trait Stream {
     /// or `None` if no value can be determined at this time.
     fn value(&mut self) -> Option<f64>;
 
+    /// Discards any internal state this data stream keeps between calls to `value`,
+    /// such as previous measurements. This is called when the update interval changes,
+    /// so that the next value is not computed from data spanning the old interval.
+    fn reset(&mut self) {}
+
     /// Returns the minimum value of the quantity represented by this data stream,
     /// or `None` to have the minimum dynamically calculated from all value samples.
     /// This method **must** return the same value each time it is called.
@@ -53,9 +63,54 @@ pub trait Stream {
         None
     }
 
+    /// Returns a value that the quantity represented by this data stream usually stays below,
+    /// but may exceed, or `None` if there is no such value. Unlike with `max`, values above it
+    /// are valid, and graphs are scaled to include both this value and any higher values.
+    /// This method **must** return the same value each time it is called.
+    fn soft_max(&self) -> Option<f64> {
+        None
+    }
+
+    /// Returns `false` if this data stream should only be shown after the user activates it,
+    /// e.g. because it provides details that are rarely needed.
+    /// This method **must** return the same value each time it is called.
+    fn active_by_default(&self) -> bool {
+        true
+    }
+
+    /// Returns `true` if the user interface should estimate, based on recent values,
+    /// when this data stream will reach its maximum. This is useful for quantities
+    /// that tend to grow steadily until they are exhausted, such as disk usage.
+    /// This method **must** return the same value each time it is called.
+    fn forecast(&self) -> bool {
+        false
+    }
+
+    /// Returns the unit of the quantity represented by this data stream (e.g. `%` or `°C`),
+    /// or an empty string if the quantity has no unit.
+    /// This method **must** return the same value each time it is called.
+    fn unit(&self) -> String {
+        String::new()
+    }
+
+    /// Returns `true` if the values of this data stream are rates per second
+    /// (e.g. bytes per second for network throughput), in which case the unit
+    /// returned by `unit` is implicitly divided by seconds.
+    /// This method **must** return the same value each time it is called.
+    fn rate(&self) -> bool {
+        false
+    }
+
+    /// Returns the function used by `format`, which must not depend on the state
+    /// of this data stream, so that values can be formatted while it is computing a value.
+    /// This method **must** return the same value each time it is called.
+    fn formatter(&self) -> Formatter;
+
     /// Returns a human-readable representation of the given value.
-    /// The result should make use of the appropriate colors from the given theme.
-    fn format(&self, value: f64, theme: &Theme) -> String;
+    /// The result makes use of the appropriate colors from the given theme.
+    fn format(&self, value: f64, theme: &Theme) -> String {
+        (self.formatter())(value, theme)
+    }
 
     /// Returns the maximum width, in characters when printed to the terminal,
     /// of all values that the `format` method can return.
@@ -63,12 +118,29 @@ pub trait Stream {
     fn format_width(&self) -> usize;
 }
 
+/// A source of values for data streams created with `Stream::new`.
+/// This trait is implemented for all closures returning `Option<f64>`,
+/// which is sufficient for sources that don't keep any internal state.
+pub trait Source: Send {
+    /// Returns the current value, or `None` if no value can be determined at this time.
+    fn value(&mut self) -> Option<f64>;
+
+    /// Discards any internal state. See `Stream::reset`.
+    fn reset(&mut self) {}
+}
+
+impl<F: FnMut() -> Option<f64> + Send> Source for F {
+    fn value(&mut self) -> Option<f64> {
+        self()
+    }
+}
+
 impl dyn Stream {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: impl Into<String>,
         description: impl Into<String>,
-        value: impl FnMut() -> Option<f64> + 'static,
+        value: impl Source + 'static,
         min: Option<f64>,
         max: Option<f64>,
         unit: impl Into<String>,
@@ -76,8 +148,9 @@ impl dyn Stream {
         precision: usize,
         signed: bool,
     ) -> Box<dyn Stream> {
-        let unit_1 = unit.into();
-        let unit_2 = unit_1.clone();
+        let unit = unit.into();
+        let unit_1 = unit.clone();
+        let unit_2 = unit.clone();
 
         let use_prefix = digits_before_decimal.is_none();
 
@@ -87,7 +160,8 @@ impl dyn Stream {
             value: Box::new(value),
             min,
             max,
-            format: Box::new(move |value: f64, theme: &Theme| {
+            unit,
+            formatter: Arc::new(move |value: f64, theme: &Theme| {
                 format_quantity(
                     value,
                     &unit_1,
@@ -112,13 +186,60 @@ impl dyn Stream {
     }
 }
 
+impl dyn Stream {
+    /// Returns this data stream with the given soft maximum (see `Stream::soft_max`).
+    pub fn with_soft_max(self: Box<Self>, soft_max: f64) -> Box<dyn Stream> {
+        Box::new(AdjustedStream {
+            stream: self,
+            soft_max: Some(soft_max),
+            active_by_default: None,
+            forecast: None,
+            rate: None,
+        })
+    }
+
+    /// Returns this data stream, inactive by default (see `Stream::active_by_default`).
+    pub fn inactive_by_default(self: Box<Self>) -> Box<dyn Stream> {
+        Box::new(AdjustedStream {
+            stream: self,
+            soft_max: None,
+            active_by_default: Some(false),
+            forecast: None,
+            rate: None,
+        })
+    }
+
+    /// Returns this data stream with forecasting enabled (see `Stream::forecast`).
+    pub fn with_forecast(self: Box<Self>) -> Box<dyn Stream> {
+        Box::new(AdjustedStream {
+            stream: self,
+            soft_max: None,
+            active_by_default: None,
+            forecast: Some(true),
+            rate: None,
+        })
+    }
+
+    /// Returns this data stream, marked as providing rates per second (see `Stream::rate`).
+    pub fn as_rate(self: Box<Self>) -> Box<dyn Stream> {
+        Box::new(AdjustedStream {
+            stream: self,
+            soft_max: None,
+            active_by_default: None,
+            forecast: None,
+            rate: Some(true),
+        })
+    }
+}
+
 struct SimpleStream {
     name: String,
     description: String,
-    value: Box<dyn FnMut() -> Option<f64>>,
+    value: Box<dyn Source>,
     min: Option<f64>,
     max: Option<f64>,
-    format: Box<dyn Fn(f64, &Theme) -> String>,
+    unit: String,
+    formatter: Formatter,
     format_width: usize,
 }
 
@@ -132,7 +253,11 @@ impl Stream for SimpleStream {
     }
 
     fn value(&mut self) -> Option<f64> {
-        (self.value)()
+        self.value.value()
+    }
+
+    fn reset(&mut self) {
+        self.value.reset();
     }
 
     fn min(&self) -> Option<f64> {
@@ -143,11 +268,79 @@ impl Stream for SimpleStream {
         self.max
     }
 
-    fn format(&self, value: f64, theme: &Theme) -> String {
-        (self.format)(value, theme)
+    fn unit(&self) -> String {
+        self.unit.clone()
+    }
+
+    fn formatter(&self) -> Formatter {
+        self.formatter.clone()
     }
 
     fn format_width(&self) -> usize {
         self.format_width
     }
 }
+
+// Overrides properties of another data stream
+struct AdjustedStream {
+    stream: Box<dyn Stream>,
+    soft_max: Option<f64>,
+    active_by_default: Option<bool>,
+    forecast: Option<bool>,
+    rate: Option<bool>,
+}
+
+impl Stream for AdjustedStream {
+    fn name(&self) -> String {
+        self.stream.name()
+    }
+
+    fn description(&self) -> String {
+        self.stream.description()
+    }
+
+    fn value(&mut self) -> Option<f64> {
+        self.stream.value()
+    }
+
+    fn reset(&mut self) {
+        self.stream.reset();
+    }
+
+    fn min(&self) -> Option<f64> {
+        self.stream.min()
+    }
+
+    fn max(&self) -> Option<f64> {
+        self.stream.max()
+    }
+
+    fn soft_max(&self) -> Option<f64> {
+        self.soft_max.or_else(|| self.stream.soft_max())
+    }
+
+    fn active_by_default(&self) -> bool {
+        self.active_by_default
+            .unwrap_or_else(|| self.stream.active_by_default())
+    }
+
+    fn forecast(&self) -> bool {
+        self.forecast.unwrap_or_else(|| self.stream.forecast())
+    }
+
+    fn unit(&self) -> String {
+        self.stream.unit()
+    }
+
+    fn rate(&self) -> bool {
+        self.rate.unwrap_or_else(|| self.stream.rate())
+    }
+
+    fn formatter(&self) -> Formatter {
+        self.stream.formatter()
+    }
+
+    fn format_width(&self) -> usize {
+        self.stream.format_width()
+    }
+}
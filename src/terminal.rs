@@ -90,7 +90,7 @@ impl Terminal {
         io::stdout().flush().unwrap();
     }
 
-    pub fn size(&self) -> (usize, usize) {
+    pub fn size() -> (usize, usize) {
         let (width, height) = termion::terminal_size().unwrap();
         (width as usize, height as usize)
     }
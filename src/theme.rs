@@ -43,6 +43,8 @@ pub struct Theme {
     pub stream_number_color: AnsiValue,
     /// Color of units in stream values
     pub stream_unit_color: AnsiValue,
+    /// Color of warnings about problems with a stream
+    pub stream_warning_color: AnsiValue,
     /// Colors of stream graphs, to be repeated cyclically.
     /// The first element in each pair is the regular color,
     /// the second the color for tick intersections.
@@ -62,6 +64,15 @@ pub struct Theme {
 }
 
 impl Theme {
+    /// Returns the theme with the given name, or `None` if no such theme exists.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "light" => Some(Theme::light()),
+            _ => None,
+        }
+    }
+
     pub fn default() -> Self {
         Theme {
             top_bar_color: AnsiValue::grayscale(4),
@@ -77,6 +88,7 @@ impl Theme {
             stream_description_color: AnsiValue::grayscale(16),
             stream_number_color: AnsiValue::grayscale(20),
             stream_unit_color: AnsiValue::grayscale(12),
+            stream_warning_color: AnsiValue::rgb(5, 3, 0),
             stream_graph_colors: vec![
                 (AnsiValue::rgb(4, 0, 0), AnsiValue::rgb(5, 1, 1)),
                 (AnsiValue::rgb(0, 4, 0), AnsiValue::rgb(1, 5, 1)),
@@ -93,4 +105,37 @@ impl Theme {
             bottom_bar_unit_color: AnsiValue::rgb(0, 0, 2),
         }
     }
+
+    pub fn light() -> Self {
+        Theme {
+            top_bar_color: AnsiValue::grayscale(19),
+            top_bar_number_color: AnsiValue::grayscale(2),
+            top_bar_unit_color: AnsiValue::grayscale(9),
+            tick_color: AnsiValue::grayscale(20),
+            stream_even_background_color: AnsiValue::grayscale(23),
+            stream_odd_background_color: AnsiValue::grayscale(22),
+            stream_selected_background_color: AnsiValue::grayscale(21),
+            stream_name_color: AnsiValue::grayscale(0),
+            stream_name_selected_text_color: AnsiValue::grayscale(23),
+            stream_name_selected_background_color: AnsiValue::grayscale(5),
+            stream_description_color: AnsiValue::grayscale(7),
+            stream_number_color: AnsiValue::grayscale(3),
+            stream_unit_color: AnsiValue::grayscale(11),
+            stream_warning_color: AnsiValue::rgb(4, 2, 0),
+            stream_graph_colors: vec![
+                (AnsiValue::rgb(4, 0, 0), AnsiValue::rgb(3, 0, 0)),
+                (AnsiValue::rgb(0, 3, 0), AnsiValue::rgb(0, 2, 0)),
+                (AnsiValue::rgb(0, 0, 4), AnsiValue::rgb(0, 0, 3)),
+                (AnsiValue::rgb(3, 3, 0), AnsiValue::rgb(2, 2, 0)),
+                (AnsiValue::rgb(4, 0, 4), AnsiValue::rgb(3, 0, 3)),
+                (AnsiValue::rgb(0, 3, 3), AnsiValue::rgb(0, 2, 2)),
+            ],
+            bottom_bar_color: AnsiValue::grayscale(8),
+            bottom_bar_key_text_color: AnsiValue::grayscale(23),
+            bottom_bar_key_background_color: AnsiValue::grayscale(3),
+            bottom_bar_label_color: AnsiValue::grayscale(23),
+            bottom_bar_number_color: AnsiValue::rgb(3, 4, 5),
+            bottom_bar_unit_color: AnsiValue::rgb(1, 2, 5),
+        }
+    }
 }
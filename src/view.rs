@@ -17,6 +17,7 @@
 use std::cmp::max;
 use std::f64;
 use std::fmt::Display;
+use std::iter;
 use std::time::Duration;
 
 use regex::Regex;
@@ -24,14 +25,29 @@ use termion::color::{Bg, Fg};
 use termion::cursor;
 use termion::style::Reset;
 
-use crate::model::{Application, MenuItem, Screen, ScrollAnchor, StreamWrapper};
+use crate::model::{Application, Interval, MenuItem, Screen, ScrollAnchor, StreamWrapper};
+use crate::processes::{Process, SortOrder};
+use crate::stream::Stream;
 use crate::theme::Theme;
 
 const EXPANDED_GRAPH_HEIGHT: usize = 5;
 
+// Forecasts further in the future than this aren't shown
+const MAX_FORECAST: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
 const STATS_LABEL: &str = "lo/hi/avg";
+const STALLED_LABEL: &str = "stalled";
+const ERROR_MARKER: &str = "!";
+
+// Widths of the columns on the processes screen, except for the name column
+const PID_WIDTH: usize = 7;
+const CPU_WIDTH: usize = 8;
+const RSS_WIDTH: usize = 8;
+const THREADS_WIDTH: usize = 7;
 
 const DOT: &str = "\u{2022}";
+const CHECKED: &str = "\u{2611}";
+const UNCHECKED: &str = "\u{2610}";
 const BARS: &[&str] = &[
     "\u{2581}", "\u{2582}", "\u{2583}", "\u{2584}", "\u{2585}", "\u{2586}", "\u{2587}", "\u{2588}",
 ];
@@ -87,7 +103,8 @@ impl Application {
                         name_width,
                         graph_width,
                         value_width,
-                        interval.tick_spacing,
+                        interval,
+                        self.time(),
                         theme,
                     );
 
@@ -114,7 +131,7 @@ impl Application {
 
                 // Render empty lines below data streams
                 if lines.len() < max_lines {
-                    let background_color = if streams.len() % 2 == 0 {
+                    let background_color = if streams.len().is_multiple_of(2) {
                         theme.stream_even_background_color
                     } else {
                         theme.stream_odd_background_color
@@ -136,16 +153,142 @@ impl Application {
             }
 
             Screen::Streams => {
-                let message = ellipsize("Stream selection is not implemented yet", self.width);
+                let width = max(self.width, 1);
+                let height = max(self.height, 3);
+
+                // Render top bar
+                let search_string = if self.streams_filter.is_empty() {
+                    format!("{}Type to search streams", Fg(theme.top_bar_unit_color))
+                } else {
+                    format!(
+                        "{}Search: {}{}",
+                        Fg(theme.top_bar_unit_color),
+                        Fg(theme.top_bar_number_color),
+                        self.streams_filter,
+                    )
+                };
 
                 string.push_str(&format!(
-                    "{}{}{}",
-                    Fg(theme.stream_name_color),
-                    Bg(theme.stream_odd_background_color),
-                    pad_right(message, self.width),
+                    "{}{}",
+                    Bg(theme.top_bar_color),
+                    pad_right(ellipsize(format!(" {}", search_string), width), width),
                 ));
 
-                string.push_str(&format!("\n\r{}", " ".repeat(self.width)).repeat(max(self.height, 2) - 2));
+                let max_lines = height - 2;
+
+                let indices = self.filtered_stream_indices();
+
+                let name_width = indices
+                    .iter()
+                    .map(|&i| printed_width(self.streams[i].stream.name()))
+                    .max()
+                    .unwrap_or(0);
+
+                let mut lines = Vec::new();
+
+                // Render stream entries
+                for (i, &index) in indices
+                    .iter()
+                    .enumerate()
+                    .skip(self.streams_scroll_index)
+                    .take(max_lines)
+                {
+                    lines.push(self.streams[index].render_entry(
+                        i,
+                        i == self.streams_selection_index,
+                        name_width,
+                        width,
+                        theme,
+                    ));
+                }
+
+                if !lines.is_empty() {
+                    string.push_str("\n\r");
+                    string.push_str(&lines.join("\n\r"));
+                }
+
+                // Render empty lines below stream entries
+                if lines.len() < max_lines {
+                    let background_color = if indices.len().is_multiple_of(2) {
+                        theme.stream_even_background_color
+                    } else {
+                        theme.stream_odd_background_color
+                    };
+
+                    string.push_str(
+                        &format!("\n\r{}{}", Bg(background_color), " ".repeat(width)).repeat(max_lines - lines.len()),
+                    );
+                }
+            }
+
+            Screen::Processes => {
+                let width = max(self.width, 1);
+                let height = max(self.height, 3);
+
+                // Render top bar, highlighting the column the table is sorted by
+                let header = |label: &str, sort_order: Option<SortOrder>| {
+                    format!(
+                        "{}{}",
+                        Fg(if sort_order == Some(self.processes.sort_order) {
+                            theme.top_bar_number_color
+                        } else {
+                            theme.top_bar_unit_color
+                        }),
+                        label,
+                    )
+                };
+
+                string.push_str(&format!(
+                    "{}{}",
+                    Bg(theme.top_bar_color),
+                    pad_right(
+                        ellipsize(
+                            process_line(
+                                header("PID", None),
+                                header("CPU", Some(SortOrder::Cpu)),
+                                header("RSS", Some(SortOrder::Memory)),
+                                header("Threads", None),
+                                header("Name", None),
+                            ),
+                            width,
+                        ),
+                        width,
+                    ),
+                ));
+
+                let max_lines = height - 2;
+
+                let processes = &self.processes.processes;
+
+                let mut lines = Vec::new();
+
+                // Render process entries
+                for (i, process) in processes
+                    .iter()
+                    .enumerate()
+                    .skip(self.processes_scroll_index)
+                    .take(max_lines)
+                {
+                    lines.push(process.render_entry(i, i == self.processes_selection_index, width, theme));
+                }
+
+                if !lines.is_empty() {
+                    string.push_str("\n\r");
+                    string.push_str(&lines.join("\n\r"));
+                }
+
+                // Render empty lines below process entries
+                if lines.len() < max_lines {
+                    let background_color = if processes.len().is_multiple_of(2) {
+                        theme.stream_even_background_color
+                    } else {
+                        theme.stream_odd_background_color
+                    };
+
+                    string.push_str(
+                        &format!("\n\r{}{}", Bg(background_color), " ".repeat(width)).repeat(max_lines - lines.len()),
+                    );
+                }
             }
         }
 
@@ -169,6 +312,21 @@ impl Application {
             );
             string.push_str(&format!(" {}", interval_string));
             menu_width += 1 + printed_width(&interval_string);
+
+            if let Some(ref replay) = self.replay {
+                let replay_string = format!(
+                    "{}{} {}",
+                    Fg(theme.bottom_bar_label_color),
+                    if replay.paused { "Paused" } else { "Replaying" },
+                    format_duration(
+                        Duration::from_millis(self.time()),
+                        Fg(theme.bottom_bar_number_color),
+                        Fg(theme.bottom_bar_unit_color),
+                    ),
+                );
+                string.push_str(&format!("  {}", replay_string));
+                menu_width += 2 + printed_width(&replay_string);
+            }
         }
 
         string.push_str("  ");
@@ -185,9 +343,16 @@ impl Application {
         self.active_streams()
             .iter()
             .map(|s| {
+                // Streams that have returned invalid values are marked next to their names
+                let marker_width = if s.errors > 0 {
+                    printed_width(ERROR_MARKER) + 1
+                } else {
+                    0
+                };
+
                 max(
                     // Any name ...
-                    printed_width(s.stream.name()),
+                    marker_width + printed_width(s.stream.name()),
                     // ... and any value must fit, because the name column
                     // also displays values (tick labels) for expanded streams
                     s.stream.format_width(),
@@ -205,8 +370,8 @@ impl Application {
                 .map(|s| s.stream.format_width())
                 .max()
                 .unwrap_or(0),
-            // ... and the stats label must fit
-            printed_width(STATS_LABEL),
+            // ... and the stats and stalled labels must fit
+            max(printed_width(STATS_LABEL), printed_width(STALLED_LABEL)),
         )
     }
 }
@@ -220,16 +385,19 @@ impl StreamWrapper {
         name_width: usize,
         graph_width: usize,
         value_width: usize,
-        tick_spacing: usize,
+        interval: Interval,
+        time: u64,
         theme: &Theme,
     ) -> Vec<String> {
+        let tick_spacing = interval.tick_spacing;
+
         let mut lines = Vec::new();
 
         let graph_color = theme.stream_graph_colors[index % theme.stream_graph_colors.len()];
 
         let background_color = if selected {
             theme.stream_selected_background_color
-        } else if index % 2 == 0 {
+        } else if index.is_multiple_of(2) {
             theme.stream_even_background_color
         } else {
             theme.stream_odd_background_color
@@ -257,7 +425,7 @@ impl StreamWrapper {
                     None => DOT,
                 };
 
-                if ((graph_width - 1) - i) % tick_spacing == 0 {
+                if ((graph_width - 1) - i).is_multiple_of(tick_spacing) {
                     // Tick intersection
                     graph.push_str(&format!(
                         "{}{}{}{}{}",
@@ -275,27 +443,28 @@ impl StreamWrapper {
             graph
         };
 
-        let values = (1..=graph_width)
-            .rev()
-            .map(|i| {
-                if i <= self.values.len() {
-                    self.values[self.values.len() - i]
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+        let buckets = self
+            .history
+            .buckets(interval.duration.as_millis() as u64, time, graph_width);
+
+        let values = buckets.iter().map(|b| b.map(|b| b.avg())).collect::<Vec<_>>();
 
         let numbers = values.iter().cloned().filter_map(|v| v).collect::<Vec<_>>();
 
-        let numbers_min = numbers.iter().cloned().fold(f64::NAN, f64::min);
-        let numbers_max = numbers.iter().cloned().fold(f64::NAN, f64::max);
-        let numbers_avg = numbers.iter().cloned().sum::<f64>() / (numbers.len() as f64);
+        let numbers_min = buckets.iter().flatten().map(|b| b.min).fold(f64::NAN, f64::min);
+        let numbers_max = buckets.iter().flatten().map(|b| b.max).fold(f64::NAN, f64::max);
+        let numbers_avg = buckets.iter().flatten().map(|b| b.sum).sum::<f64>()
+            / (buckets.iter().flatten().map(|b| b.count).sum::<usize>() as f64);
 
         let min = self.stream.min().unwrap_or(numbers_min);
-        let max = self.stream.max().unwrap_or(numbers_max);
-
-        let value_string = if numbers.is_empty() {
+        let max = self.stream.max().unwrap_or_else(|| match self.stream.soft_max() {
+            Some(soft_max) => soft_max.max(numbers_max),
+            None => numbers_max,
+        });
+
+        let value_string = if self.stalled() {
+            format!("{}{}", Fg(theme.stream_warning_color), STALLED_LABEL)
+        } else if numbers.is_empty() {
             String::new()
         } else {
             self.stream.format(numbers[numbers.len() - 1], theme)
@@ -315,14 +484,52 @@ impl StreamWrapper {
             }),
         );
 
-        line.push_str(&pad_left(self.stream.name(), name_width));
+        let name = if self.errors > 0 {
+            format!(
+                "{}{}{} {}",
+                Fg(theme.stream_warning_color),
+                ERROR_MARKER,
+                Fg(if selected {
+                    theme.stream_name_selected_text_color
+                } else {
+                    theme.stream_name_color
+                }),
+                self.stream.name(),
+            )
+        } else {
+            self.stream.name()
+        };
+
+        line.push_str(&pad_left(name, name_width));
         line.push_str(&format!("{} ", Bg(background_color)));
 
         if self.expanded {
+            let mut description = self.stream.description();
+
+            if self.stream.forecast() {
+                let resolution = interval.duration.as_millis() as u64;
+                if let Some(duration) = self.stream.max().and_then(|max| time_to_max(&values, max, resolution)) {
+                    description.push_str(&format!(
+                        " {}(full in {}){}",
+                        Fg(theme.stream_warning_color),
+                        format_duration(duration, "", ""),
+                        Fg(theme.stream_description_color),
+                    ));
+                }
+            }
+
+            if self.errors > 0 {
+                description.push_str(&format!(
+                    " {}({} invalid values)",
+                    Fg(theme.stream_warning_color),
+                    self.errors,
+                ));
+            }
+
             line.push_str(&format!(
                 "{}{} {}",
                 Fg(theme.stream_description_color),
-                pad_right(ellipsize(self.stream.description(), graph_width), graph_width),
+                pad_right(ellipsize(description, graph_width), graph_width),
                 pad_right(value_string, value_width),
             ));
 
@@ -429,6 +636,96 @@ impl StreamWrapper {
             1
         }
     }
+
+    fn render_entry(&self, index: usize, selected: bool, name_width: usize, width: usize, theme: &Theme) -> String {
+        let background_color = if selected {
+            theme.stream_selected_background_color
+        } else if index.is_multiple_of(2) {
+            theme.stream_even_background_color
+        } else {
+            theme.stream_odd_background_color
+        };
+
+        let mut line = format!(
+            "{}{} {} {}{}",
+            Fg(theme.stream_name_color),
+            Bg(background_color),
+            if self.active { CHECKED } else { UNCHECKED },
+            Fg(if selected {
+                theme.stream_name_selected_text_color
+            } else {
+                theme.stream_name_color
+            }),
+            Bg(if selected {
+                theme.stream_name_selected_background_color
+            } else {
+                background_color
+            }),
+        );
+
+        line.push_str(&pad_right(self.stream.name(), name_width));
+        line.push_str(&format!(
+            "{}{}  ",
+            Fg(theme.stream_description_color),
+            Bg(background_color)
+        ));
+        line.push_str(&self.stream.description());
+
+        pad_right(ellipsize(line, width), width)
+    }
+}
+
+impl Process {
+    fn render_entry(&self, index: usize, selected: bool, width: usize, theme: &Theme) -> String {
+        let background_color = if selected {
+            theme.stream_selected_background_color
+        } else if index.is_multiple_of(2) {
+            theme.stream_even_background_color
+        } else {
+            theme.stream_odd_background_color
+        };
+
+        let line = process_line(
+            format!("{}{}", Fg(theme.stream_description_color), self.pid),
+            match self.cpu {
+                Some(cpu) => format_quantity(
+                    cpu,
+                    "%",
+                    false,
+                    1,
+                    Fg(theme.stream_number_color),
+                    Fg(theme.stream_unit_color),
+                ),
+                None => String::new(),
+            },
+            format_quantity(
+                self.rss as f64,
+                "B",
+                true,
+                1,
+                Fg(theme.stream_number_color),
+                Fg(theme.stream_unit_color),
+            ),
+            format!("{}{}", Fg(theme.stream_number_color), self.threads),
+            format!(
+                "{}{}{}{}",
+                Fg(if selected {
+                    theme.stream_name_selected_text_color
+                } else {
+                    theme.stream_name_color
+                }),
+                Bg(if selected {
+                    theme.stream_name_selected_background_color
+                } else {
+                    background_color
+                }),
+                self.name,
+                Bg(background_color),
+            ),
+        );
+
+        format!("{}{}", Bg(background_color), pad_right(ellipsize(line, width), width))
+    }
 }
 
 impl MenuItem {
@@ -448,6 +745,24 @@ impl MenuItem {
     }
 }
 
+// Lays out the columns of the processes screen
+fn process_line(
+    pid: impl AsRef<str>,
+    cpu: impl AsRef<str>,
+    rss: impl AsRef<str>,
+    threads: impl AsRef<str>,
+    name: impl AsRef<str>,
+) -> String {
+    format!(
+        "{} {} {} {}  {}",
+        pad_left(pid, PID_WIDTH),
+        pad_left(cpu, CPU_WIDTH),
+        pad_left(rss, RSS_WIDTH),
+        pad_left(threads, THREADS_WIDTH),
+        name.as_ref(),
+    )
+}
+
 pub fn format_quantity(
     quantity: f64,
     unit: impl Display,
@@ -500,7 +815,7 @@ pub fn format_quantity(
     format!("{}{}{}{}{}", number_style, number, unit_style, prefix, unit)
 }
 
-fn format_duration(duration: Duration, number_style: impl Display, unit_style: impl Display) -> String {
+pub fn format_duration(duration: Duration, number_style: impl Display, unit_style: impl Display) -> String {
     let mut milliseconds = duration.as_millis();
 
     if milliseconds == 0 {
@@ -533,6 +848,64 @@ fn format_duration(duration: Duration, number_style: impl Display, unit_style: i
     string
 }
 
+/// Estimates how long it will take for the given values, which are `resolution` milliseconds
+/// apart, to reach the given maximum if they continue to grow at their average rate.
+/// Returns `None` if the values aren't growing or if the maximum is too far away to matter.
+fn time_to_max(values: &[Option<f64>], max: f64, resolution: u64) -> Option<Duration> {
+    let points = values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.map(|v| (i as f64, v)))
+        .collect::<Vec<_>>();
+
+    if points.len() < 2 {
+        return None;
+    }
+
+    // Least squares fit of a line through the points
+    let n = points.len() as f64;
+    let x_mean = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let y_mean = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance = points.iter().map(|p| (p.0 - x_mean) * (p.1 - y_mean)).sum::<f64>();
+    let variance = points.iter().map(|p| (p.0 - x_mean).powi(2)).sum::<f64>();
+
+    // Growth per millisecond
+    let slope = covariance / variance / (resolution as f64);
+
+    if slope.is_nan() || slope <= 0.0 {
+        return None;
+    }
+
+    let last = points[points.len() - 1].1;
+    let milliseconds = (max - last).max(0.0) / slope;
+
+    if milliseconds > MAX_FORECAST.as_millis() as f64 {
+        return None;
+    }
+
+    // More precision would be misleading
+    let minutes = (milliseconds / 60_000.0).round() as u64;
+    Some(Duration::from_secs(minutes.max(1) * 60))
+}
+
+/// Parses a duration such as `500ms`, `2s`, `1.5m` or `1h`.
+/// A number without a unit is interpreted as seconds.
+pub fn parse_duration(string: &str) -> Option<Duration> {
+    let regex = Regex::new(r"^(\d+(?:\.\d+)?)(ms|s|m|h)?$").unwrap();
+
+    let captures = regex.captures(string.trim())?;
+    let number = captures[1].parse::<f64>().ok()?;
+
+    let factor = match captures.get(2).map(|m| m.as_str()) {
+        Some("ms") => 1.0,
+        Some("m") => 60_000.0,
+        Some("h") => 3_600_000.0,
+        _ => 1_000.0,
+    };
+
+    Some(Duration::from_millis((number * factor).round() as u64))
+}
+
 pub fn printed_width(string: impl AsRef<str>) -> usize {
     // Matches ANSI SGR control sequences (text attributes),
     // which don't affect the printed width
@@ -540,17 +913,40 @@ pub fn printed_width(string: impl AsRef<str>) -> usize {
     regex.replace_all(string.as_ref(), "").chars().count()
 }
 
+// Truncates the string to the given printed width, marking truncation with an ellipsis.
+// ANSI SGR control sequences are preserved.
 fn ellipsize(string: impl Into<String>, width: usize) -> String {
     assert!(width > 0);
 
     let s = string.into();
 
-    if s.chars().count() > width {
-        let truncated_string: String = s.chars().take(width - 1).collect();
-        format!("{}\u{2026}", truncated_string)
-    } else {
-        s
+    if printed_width(&s) <= width {
+        return s;
     }
+
+    let regex = Regex::new(r"\x1B\[.*?m").unwrap();
+
+    let mut result = String::new();
+    let mut printed = 0;
+    let mut last_end = 0;
+
+    for m in regex.find_iter(&s).map(Some).chain(iter::once(None)) {
+        let (start, end) = m.map_or((s.len(), s.len()), |m| (m.start(), m.end()));
+
+        for c in s[last_end..start].chars() {
+            if printed == width - 1 {
+                result.push('\u{2026}');
+                return result;
+            }
+            result.push(c);
+            printed += 1;
+        }
+
+        result.push_str(&s[start..end]);
+        last_end = end;
+    }
+
+    result
 }
 
 fn pad_left(string: impl AsRef<str>, width: usize) -> String {
@@ -619,6 +1015,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ellipsize() {
+        assert_eq!(ellipsize("abc", 3), "abc");
+        assert_eq!(ellipsize("abcd", 3), "ab\u{2026}");
+        assert_eq!(
+            ellipsize(format!("{}ab{}cd", Fg(Red), Bg(Green)), 3),
+            format!("{}ab{}\u{2026}", Fg(Red), Bg(Green)),
+        );
+        assert_eq!(
+            ellipsize(format!("a{}bcd", Fg(Red)), 2),
+            format!("a{}\u{2026}", Fg(Red)),
+        );
+    }
+
+    #[test]
+    fn test_time_to_max() {
+        let values = vec![None, Some(10.0), Some(20.0), None, Some(40.0)];
+        assert_eq!(time_to_max(&values, 100.0, 60_000), Some(Duration::from_secs(6 * 60)));
+        assert_eq!(time_to_max(&values, 40.0, 60_000), Some(Duration::from_secs(60)));
+        assert_eq!(time_to_max(&[Some(1.0), Some(1.0)], 100.0, 60_000), None);
+        assert_eq!(time_to_max(&[Some(2.0), Some(1.0)], 100.0, 60_000), None);
+        assert_eq!(time_to_max(&[Some(1.0)], 100.0, 60_000), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("0.1s"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_duration("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1m"), Some(Duration::from_secs(60)));
+        assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("1d"), None);
+        assert_eq!(parse_duration("-1s"), None);
+    }
+
     #[test]
     fn test_printed_width() {
         assert_eq!(printed_width(""), 0);